@@ -0,0 +1,41 @@
+#[cfg(feature = "sdl")]
+use sdl2;
+
+use hztrack::mixer::{Backend, NullBackend, WavBackend};
+#[cfg(feature = "sdl")]
+use hztrack::mixer::SdlBackend;
+
+// The output named by $HZTRACK_AUDIO, or SDL by default.
+pub fn default_output() -> String {
+    ::std::env::var("HZTRACK_AUDIO").unwrap_or("sdl".to_string())
+}
+
+// Open an audio output by name: "sdl", "null", or "wav:<path>". SDL is
+// only started for its own output.
+pub fn open(name: &str) -> Result<Box<dyn Backend>, String> {
+    match name {
+        "sdl" => open_sdl(),
+        "null" => Ok(Box::new(NullBackend::new())),
+        _ if name.starts_with("wav:") => Ok(Box::new(WavBackend::new(&name[4..]))),
+        _ => Err(format!("unknown audio output: {}", name)),
+    }
+}
+
+// Like open, for when SDL is already running, as it can't be started twice.
+#[cfg(feature = "sdl")]
+pub fn open_in(sdl: &sdl2::Sdl, name: &str) -> Result<Box<dyn Backend>, String> {
+    match name {
+        "sdl" => Ok(Box::new(SdlBackend::new(sdl)?)),
+        _ => open(name),
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn open_sdl() -> Result<Box<dyn Backend>, String> {
+    open_in(&sdl2::init()?, "sdl")
+}
+
+#[cfg(not(feature = "sdl"))]
+fn open_sdl() -> Result<Box<dyn Backend>, String> {
+    Err("built without SDL audio".to_string())
+}
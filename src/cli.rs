@@ -0,0 +1,226 @@
+// Command line use, for scripts and build pipelines. Errors go to stderr,
+// and the exit code is 0 on success, 1 on failure and 2 on bad usage.
+
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hztrack::file;
+use hztrack::keymap;
+use hztrack::mixer::{Settings, Clip};
+use hztrack::player;
+use hztrack::render;
+use hztrack::track::{Edit, Transport};
+use term::{Terminal, PlayerView, Editor};
+
+const USAGE: &'static str = "\
+usage: hztrack                      open the editor
+       hztrack play <song> [--seconds N] [--output sdl|null|wav:<path>] [mix]
+       hztrack edit <song> [--output sdl|null|wav:<path>]
+       hztrack tui <song> [--output sdl|null|wav:<path>]
+       hztrack render <song> -o <out.wav> [--seconds N] [--rate HZ] [mix]
+       hztrack info <song>
+       hztrack convert <in> <out.hzt>
+mix:   --gain N (0.25 by default) --clip hard|soft|limit";
+
+// Sample rates that render accepts.
+const MIN_RATE: u32 = 8000;
+const MAX_RATE: u32 = 192000;
+
+enum Error {
+    Usage(String),
+    Failed(String),
+}
+
+impl From<String> for Error {
+    fn from(e: String) -> Error { Error::Failed(e) }
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let result = match args.first().map(|a| a.as_str()) {
+        Some("play") => play(&args[1..]),
+        Some("edit") => edit(&args[1..]),
+        Some("tui") => tui(&args[1..]),
+        Some("render") => render(&args[1..]),
+        Some("info") => info(&args[1..]),
+        Some("convert") => convert(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(cmd) => Err(Error::Usage(format!("unknown command: {}", cmd))),
+        None => Err(Error::Usage("built without the graphical editor".to_string())),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(Error::Failed(e)) => {
+            eprintln!("hztrack: {}", e);
+            1
+        }
+        Err(Error::Usage(e)) => {
+            eprintln!("hztrack: {}\n{}", e, USAGE);
+            2
+        }
+    }
+}
+
+// Positional arguments and --options of a subcommand.
+struct Args {
+    free:   Vec<String>,
+    opts:   Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(args: &[String], known: &[&str]) -> Result<Args, Error> {
+        let mut out = Args { free: vec![], opts: vec![] };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg.starts_with('-') {
+                if !known.contains(&arg.as_str()) {
+                    return Err(Error::Usage(format!("unknown option: {}", arg)));
+                }
+                let value = args.next().ok_or_else(||
+                    Error::Usage(format!("{} needs a value", arg)))?;
+                out.opts.push((arg.clone(), value.clone()));
+            } else {
+                out.free.push(arg.clone());
+            }
+        }
+        Ok(out)
+    }
+    fn free(&self, n: usize) -> Result<Vec<&str>, Error> {
+        if self.free.len() != n {
+            return Err(Error::Usage("wrong number of arguments".to_string()));
+        }
+        Ok(self.free.iter().map(|s| s.as_str()).collect())
+    }
+    fn opt(&self, name: &str) -> Option<&str> {
+        self.opts.iter().rev().find(|o| o.0 == name).map(|o| o.1.as_str())
+    }
+    fn num<T: ::std::str::FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        match self.opt(name) {
+            None => Ok(None),
+            Some(v) => v.parse().map(Some).map_err(|_|
+                Error::Usage(format!("bad value for {}: {}", name, v))),
+        }
+    }
+}
+
+// Mixer settings from --gain and --clip.
+fn settings(args: &Args) -> Result<Settings, Error> {
+    let mut settings = Settings::default();
+    if let Some(gain) = args.num::<f32>("--gain")? {
+        if !(0.0..=16.0).contains(&gain) {
+            return Err(Error::Usage("gain must be 0 to 16".to_string()));
+        }
+        settings.gain = gain;
+    }
+    settings.clip = match args.opt("--clip") {
+        None => settings.clip,
+        Some("hard") => Clip::Hard,
+        Some("soft") => Clip::Soft,
+        Some("limit") => Clip::Limit,
+        Some(clip) => return Err(Error::Usage(format!("bad value for --clip: {}", clip))),
+    };
+    Ok(settings)
+}
+
+fn play(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &["--seconds", "--output", "--gain", "--clip"])?;
+    let song = args.free(1)?[0];
+    let seconds = args.num::<f64>("--seconds")?;
+    let settings = settings(&args)?;
+    let output = args.opt("--output").map_or(::audio::default_output(), |o| o.to_string());
+    let mut track = file::load(song)?;
+    let seconds = seconds.unwrap_or_else(|| track.analyze().seconds);
+    track.play_song();
+    let (player, mut remote) = player::new(track);
+    let mut audio = ::audio::open(&output)?;
+    audio.start(Box::new(player), &settings)?;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis((seconds * 1000.0) as u64) {
+        if remote.status().transport == Transport::Stopped {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    remote.send(Edit::Stop);
+    Ok(())
+}
+
+// Like play, but interactive, showing the pattern in the terminal.
+fn tui(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &["--output"])?;
+    let song = args.free(1)?[0];
+    let output = args.opt("--output").map_or(::audio::default_output(), |o| o.to_string());
+    let track = file::load(song)?;
+    let (mut view, player) = PlayerView::new(track);
+    let mut audio = ::audio::open(&output)?;
+    audio.start(Box::new(player), &Settings::default())?;
+    let mut term = Terminal::raw()?;
+    view.run(&mut term);
+    Ok(())
+}
+
+// Edit a song in the terminal, starting a new one if it doesn't exist.
+// Modules are saved alongside in hztrack's own format.
+fn edit(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &["--output"])?;
+    let song = Path::new(args.free(1)?[0]);
+    let output = args.opt("--output").map_or(::audio::default_output(), |o| o.to_string());
+    let track = if song.exists() {
+        file::load(song)?
+    } else {
+        ::session::new_song()
+    };
+    let is_mod = song.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mod"));
+    let path = if is_mod { song.with_extension("hzt") } else { song.to_path_buf() };
+    let keymap = keymap::load()?;
+    let (mut editor, player) = Editor::new(track, path, keymap);
+    let mut audio = ::audio::open(&output)?;
+    audio.start(Box::new(player), &Settings::default())?;
+    let mut term = Terminal::raw()?;
+    editor.run(&mut term);
+    Ok(())
+}
+
+fn render(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &["-o", "--seconds", "--rate", "--gain", "--clip"])?;
+    let song = args.free(1)?[0];
+    let out = args.opt("-o").ok_or(Error::Usage("no output file given".to_string()))?;
+    let seconds = args.num::<f64>("--seconds")?;
+    let rate = args.num::<u32>("--rate")?.unwrap_or(48000);
+    if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+        return Err(Error::Usage(format!("rate must be {} to {} Hz", MIN_RATE, MAX_RATE)));
+    }
+    let settings = settings(&args)?;
+    let track = file::load(song)?;
+    render::render_wav(track, out, rate, seconds, &settings)?;
+    Ok(())
+}
+
+fn info(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &[])?;
+    let song = args.free(1)?[0];
+    let track = file::load(song)?;
+    let analysis = track.analyze();
+    println!("channels:    {}", track.seq.width());
+    println!("rows:        {}", track.seq.len());
+    println!("instruments: {}", track.instruments.len());
+    println!("rows played: {}", analysis.rows);
+    println!("duration:    {:.2}s", analysis.seconds);
+    match analysis.loop_row {
+        Some(row) => println!("loops to:    row {}", row),
+        None => println!("loops to:    none"),
+    }
+    Ok(())
+}
+
+fn convert(args: &[String]) -> Result<(), Error> {
+    let args = Args::parse(args, &[])?;
+    let files = args.free(2)?;
+    let track = file::load(files[0])?;
+    file::save(&track, files[1])?;
+    Ok(())
+}
@@ -0,0 +1,253 @@
+// Rectangular blocks of fields: marking them, and cutting and pasting them
+// through the editor's clipboard.
+
+use editor::Editor;
+use file::{write_rows, parse_rows};
+use sequence::Field;
+use track::{Edit, COMMANDS};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Block {
+    pub row:    usize,
+    pub chan:   usize,
+    pub height: usize,
+    pub width:  usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Paste {
+    Over,   // replace what's there
+    Mix,    // only fill empty fields
+    Flood,  // repeat down to the end of the pattern
+}
+
+impl Block {
+    pub fn contains(&self, row: usize, chan: usize) -> bool {
+        row >= self.row && row < self.row + self.height &&
+            chan >= self.chan && chan < self.chan + self.width
+    }
+}
+
+impl Editor {
+    // The marked block, from the mark to the cursor, or else just the field
+    // under the cursor.
+    pub fn block(&self) -> Block {
+        let (row, chan) = (self.row, self.chan());
+        let (mark_row, mark_chan) = self.mark.map_or((row, chan), |(r, c)| {
+            (r.min(self.seq.len() - 1), c.min(self.seq.width() - 1))
+        });
+        Block {
+            row: row.min(mark_row),
+            chan: chan.min(mark_chan),
+            height: (row as isize - mark_row as isize).unsigned_abs() + 1,
+            width: (chan as isize - mark_chan as isize).unsigned_abs() + 1,
+        }
+    }
+    pub fn marked(&self) -> bool { self.mark.is_some() }
+    pub fn clipboard(&self) -> &[Vec<Field>] { &self.clipboard }
+    // The clipboard in the text pattern format, for other programs.
+    pub fn clipboard_text(&self) -> String {
+        write_rows(&self.clipboard)
+    }
+    // Text that isn't a block of fields is refused, leaving the clipboard as
+    // it was.
+    pub fn set_clipboard_text(&mut self, text: &str) -> Result<(), String> {
+        let rows = parse_rows(text)?;
+        if rows.is_empty() {
+            return Err("nothing to paste".to_string());
+        }
+        let unknown = rows.iter().flat_map(|r| r.iter())
+            .find(|f| !COMMANDS.contains(f.cmd.id as char));
+        if let Some(field) = unknown {
+            return Err(format!("unknown command id: {}", field.cmd.id as char));
+        }
+        self.clipboard = rows;
+        Ok(())
+    }
+
+    pub(super) fn toggle_mark(&mut self) {
+        self.mark = match self.mark {
+            Some(_) => None,
+            None => Some((self.row, self.chan())),
+        };
+    }
+    pub(super) fn copy(&mut self) {
+        let b = self.block();
+        self.clipboard = self.seq.fields[b.row..b.row + b.height].iter()
+            .map(|row| row[b.chan..b.chan + b.width].to_vec())
+            .collect();
+    }
+    pub(super) fn cut(&mut self) {
+        self.copy();
+        let b = self.block();
+        for row in b.row..b.row + b.height {
+            for chan in b.chan..b.chan + b.width {
+                self.edit(Edit::SetField(row, chan, Field::blank()));
+            }
+        }
+    }
+    // Paste the clipboard with its top left at the cursor, cut off at the
+    // edges of the pattern.
+    pub(super) fn paste(&mut self, how: Paste) {
+        let clip = self.clipboard.clone();
+        if clip.is_empty() {
+            return;
+        }
+        let (top, left) = (self.row, self.chan());
+        let height = match how {
+            Paste::Flood => self.seq.len() - top,
+            _ => clip.len().min(self.seq.len() - top),
+        };
+        for y in 0..height {
+            let src = &clip[y % clip.len()];
+            for (x, field) in src.iter().enumerate() {
+                let (row, chan) = (top + y, left + x);
+                if chan >= self.seq.width() {
+                    break;
+                }
+                if how == Paste::Mix && !self.seq.get_field(row, chan).is_empty() {
+                    continue;
+                }
+                let edit = Edit::SetField(row, chan, field.clone());
+                self.edit(edit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Block;
+    use editor::{Editor, Action};
+    use file::{parse_rows, write_rows};
+    use sequence::{Sequence, Field};
+
+    const PATTERN: &str = "\
+C-4000 D-4C20    000
+   000 E-4000    000
+   000    000 ---000
+   000    000    000
+";
+
+    fn editor() -> Editor {
+        Editor::new(Sequence::new(vec![vec![Field::blank(); 2]; 4]))
+    }
+    fn pattern() -> Editor {
+        Editor::new(Sequence::new(parse_rows(PATTERN).unwrap()))
+    }
+    fn rows(ed: &Editor) -> String {
+        write_rows(&ed.seq().fields)
+    }
+    // Mark the top left two by two fields and copy them.
+    fn copy_corner(ed: &mut Editor) {
+        ed.act(Action::Mark);
+        ed.act(Action::Move(4, 1));
+        assert_eq!(ed.block(), Block { row: 0, chan: 0, height: 2, width: 2 });
+        ed.act(Action::Copy);
+        ed.act(Action::Mark);
+    }
+
+    #[test]
+    fn block_without_mark_is_the_cursor() {
+        let mut ed = pattern();
+        ed.act(Action::Move(4, 2));
+        assert!(!ed.marked());
+        assert_eq!(ed.block(), Block { row: 2, chan: 1, height: 1, width: 1 });
+    }
+
+    #[test]
+    fn copy_and_paste_over() {
+        let mut ed = pattern();
+        copy_corner(&mut ed);
+        assert_eq!(write_rows(ed.clipboard()), "C-4000 D-4C20\n   000 E-4000\n");
+        ed.act(Action::Move(0, 1));
+        ed.act(Action::Paste);
+        assert_eq!(rows(&ed), "\
+C-4000 D-4C20    000
+   000 E-4000    000
+   000 C-4000 D-4C20
+   000    000 E-4000
+");
+    }
+
+    #[test]
+    fn cut_blanks_the_block() {
+        let mut ed = pattern();
+        ed.act(Action::Mark);
+        ed.act(Action::Move(4, 1));
+        ed.act(Action::Cut);
+        assert_eq!(write_rows(ed.clipboard()), "C-4000 D-4C20\n   000 E-4000\n");
+        assert_eq!(rows(&ed), "   000    000    000
+   000    000    000
+   000    000 ---000
+   000    000    000
+");
+        ed.act(Action::Undo);
+        assert_eq!(rows(&ed), PATTERN);
+    }
+
+    #[test]
+    fn paste_is_cut_off_at_the_edges() {
+        let mut ed = pattern();
+        copy_corner(&mut ed);
+        ed.act(Action::Bottom);
+        ed.act(Action::Move(4, 0));
+        ed.act(Action::Paste);
+        assert_eq!(rows(&ed), "\
+C-4000 D-4C20    000
+   000 E-4000    000
+   000    000 ---000
+   000    000 C-4000
+");
+    }
+
+    #[test]
+    fn mix_paste_only_fills_empty_fields() {
+        let mut ed = pattern();
+        copy_corner(&mut ed);
+        ed.act(Action::Move(0, 1));
+        ed.act(Action::MixPaste);
+        assert_eq!(rows(&ed), "\
+C-4000 D-4C20    000
+   000 E-4000    000
+   000 C-4000 ---000
+   000    000 E-4000
+");
+    }
+
+    #[test]
+    fn paste_flood_repeats_to_the_end() {
+        let mut ed = pattern();
+        ed.act(Action::Move(8, 2));
+        ed.act(Action::Copy);
+        ed.act(Action::Top);
+        ed.act(Action::PasteFlood);
+        assert_eq!(rows(&ed), "\
+C-4000 D-4C20 ---000
+   000 E-4000 ---000
+   000    000 ---000
+   000    000 ---000
+");
+        ed.act(Action::Undo);
+        assert_eq!(rows(&ed), PATTERN);
+    }
+
+    #[test]
+    fn clipboard_text_round_trip() {
+        let mut ed = editor();
+        ed.set_clipboard_text("C-4C20 D-4000\n   000 ---000\n").unwrap();
+        assert_eq!(ed.clipboard().len(), 2);
+        assert_eq!(ed.clipboard_text(), "C-4C20 D-4000\n   000 ---000\n");
+    }
+
+    #[test]
+    fn other_text_keeps_clipboard() {
+        let mut ed = editor();
+        ed.set_clipboard_text("C-4C20\n").unwrap();
+        for text in &["", "hello", "C-4C20\u{e9}D-4000", "\u{e9}\u{e9}\u{e9}\u{e9}",
+                "C-4C2\u{e9}", "C-4C20 D-4000\nC-4C20", "C-4X20"] {
+            assert!(ed.set_clipboard_text(text).is_err(), "{:?}", text);
+        }
+        assert_eq!(ed.clipboard_text(), "C-4C20\n");
+    }
+}
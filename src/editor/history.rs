@@ -0,0 +1,186 @@
+// Undo and redo of pattern edits. Each action is one step, except that
+// typing into the same field again joins the step before it.
+
+use std::mem;
+
+use track::Edit;
+
+const LIMIT: usize = 4 << 20;   // bytes of edits to keep, by default
+
+pub struct History {
+    undo:   Vec<Step>,
+    redo:   Vec<Step>,
+    size:   usize,  // bytes held by both stacks
+    limit:  usize,
+}
+
+pub struct Step {
+    pub edits:  Vec<Edit>,  // as made
+    pub undo:   Vec<Edit>,  // what reverts them, to be applied last first
+    pub cursor: (usize, usize), // row and column before the step
+    typing:     Option<(usize, usize)>, // field typed into, if joinable
+}
+
+impl Step {
+    pub fn new(cursor: (usize, usize), typing: Option<(usize, usize)>) -> Self {
+        Step {
+            edits: vec![],
+            undo: vec![],
+            cursor: cursor,
+            typing: typing,
+        }
+    }
+    fn size(&self) -> usize {
+        (self.edits.len() + self.undo.len()) * mem::size_of::<Edit>()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            undo: vec![],
+            redo: vec![],
+            size: 0,
+            limit: LIMIT,
+        }
+    }
+    pub fn can_undo(&self) -> bool { !self.undo.is_empty() }
+    pub fn can_redo(&self) -> bool { !self.redo.is_empty() }
+
+    pub fn set_limit(&mut self, bytes: usize) {
+        self.limit = bytes;
+        self.trim();
+    }
+    pub fn push(&mut self, mut step: Step) {
+        if step.edits.is_empty() {
+            // something other than typing came between.
+            if let Some(last) = self.undo.last_mut() {
+                last.typing = None;
+            }
+            return;
+        }
+        for old in self.redo.drain(..) {
+            self.size -= old.size();
+        }
+        self.size += step.size();
+        match self.undo.last_mut() {
+            Some(ref mut last) if step.typing.is_some() && last.typing == step.typing => {
+                last.edits.append(&mut step.edits);
+                last.undo.append(&mut step.undo);
+            }
+            _ => self.undo.push(step),
+        }
+        self.trim();
+    }
+    pub fn undo(&mut self) -> Option<&Step> {
+        let mut step = self.undo.pop()?;
+        step.typing = None;
+        self.redo.push(step);
+        self.redo.last()
+    }
+    pub fn redo(&mut self) -> Option<&Step> {
+        let step = self.redo.pop()?;
+        self.undo.push(step);
+        self.undo.last()
+    }
+    // Forget steps until under the limit: the oldest undo steps first, then
+    // the furthest redo steps, always keeping the latest undo step.
+    fn trim(&mut self) {
+        while self.size > self.limit {
+            let old = if self.undo.len() > 1 {
+                self.undo.remove(0)
+            } else if !self.redo.is_empty() {
+                self.redo.remove(0)
+            } else {
+                break;
+            };
+            self.size -= old.size();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, Step};
+    use editor::{Editor, Action};
+    use sequence::{Sequence, Field, Note};
+    use track::Edit;
+
+    fn editor() -> Editor {
+        Editor::new(Sequence::new(vec![vec![Field::blank(); 2]; 4]))
+    }
+    fn field(ed: &Editor, row: usize) -> String {
+        ed.seq().get_field(row, 0).to_string()
+    }
+    fn step() -> Step {
+        let mut step = Step::new((0, 0), None);
+        step.edits.push(Edit::SetField(0, 0, Field::blank()));
+        step.undo.push(Edit::SetField(0, 0, Field::blank()));
+        step
+    }
+
+    #[test]
+    fn undo_and_redo_restore_fields_and_cursor() {
+        let mut ed = editor();
+        ed.act(Action::Note(Note::On(0)));
+        ed.act(Action::Note(Note::On(2)));
+        ed.act(Action::Undo);
+        assert_eq!((field(&ed, 1), ed.row()), ("   000".to_string(), 1));
+        ed.act(Action::Undo);
+        assert_eq!((field(&ed, 0), ed.row()), ("   000".to_string(), 0));
+        assert!(!ed.can_undo());
+        ed.act(Action::Redo);
+        assert_eq!(field(&ed, 0), "C-4000");
+        assert!(ed.can_redo());
+        ed.act(Action::Note(Note::On(4)));
+        assert!(!ed.can_redo());
+    }
+
+    #[test]
+    fn typing_into_one_field_is_one_step() {
+        let mut ed = editor();
+        ed.act(Action::Move(1, 0));
+        ed.act(Action::CommandId(b'C'));
+        ed.act(Action::Move(1, 0));
+        ed.act(Action::Hex(0x2));
+        ed.act(Action::Hex(0x0));
+        assert_eq!(field(&ed, 0), "   C20");
+        ed.act(Action::Undo);
+        assert_eq!(field(&ed, 0), "   C00");
+        ed.act(Action::Undo);
+        assert_eq!(field(&ed, 0), "   000");
+        assert!(!ed.can_undo());
+    }
+
+    #[test]
+    fn other_actions_end_the_typing_step() {
+        let mut ed = editor();
+        ed.act(Action::StepDown);
+        ed.act(Action::Note(Note::On(0)));
+        ed.act(Action::Note(Note::On(2)));
+        ed.act(Action::Move(0, 0));
+        ed.act(Action::Note(Note::On(4)));
+        ed.act(Action::Undo);
+        assert_eq!(field(&ed, 0), "D-4000");
+        ed.act(Action::Undo);
+        assert_eq!(field(&ed, 0), "   000");
+    }
+
+    #[test]
+    fn trim_keeps_size_under_the_limit() {
+        let size = step().size();
+        let mut history = History::new();
+        history.set_limit(size * 2);
+        for _ in 0..3 {
+            history.push(step());
+        }
+        assert_eq!((history.undo.len(), history.size), (2, size * 2));
+        history.undo();
+        history.undo();
+        history.set_limit(size);
+        assert_eq!((history.undo.len(), history.redo.len()), (0, 1));
+        assert_eq!(history.size, size);
+        history.set_limit(0);
+        assert_eq!((history.redo.len(), history.size), (0, 0));
+    }
+}
@@ -0,0 +1,456 @@
+// The pattern editor, apart from any frontend: a cursor over a sequence, and
+// the actions that move it or change the pattern. Frontends turn key presses
+// into actions, and pass the edits that come out on to the player.
+
+use std::mem;
+
+use sequence::{Sequence, Field, Note, Command};
+use track::{Edit, COMMANDS};
+
+mod block;
+mod history;
+mod ops;
+pub use self::block::Block;
+use self::block::Paste;
+use self::history::{History, Step};
+
+const PAGE: usize = 16;
+const OCTAVE: u8 = 4;       // entered notes start from here, to be audible
+const MAX_OCTAVE: u8 = 7;   // note keys span three octaves, up to MAX_NOTE
+const MAX_STEP: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Column {
+    Note,
+    CommandId,
+    CommandHi,
+    CommandLo,
+}
+
+#[derive(Clone)]
+pub enum Action {
+    Move(i32, i32),     // columns right, rows down; wraps around the edges
+    NextChannel,
+    PrevChannel,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    InsertRow,          // below the cursor, moving onto it
+    RemoveRow,
+    Clear,              // empty the column under the cursor
+    Note(Note),         // within the keyboard's octaves, from the current one
+    CommandId(u8),
+    Hex(u8),
+    Undo,
+    Redo,
+    Mark,               // start a block at the cursor, or drop it
+    Copy,
+    Cut,
+    Paste,
+    MixPaste,           // only into empty fields
+    PasteFlood,         // repeatedly, to the end of the pattern
+    Transpose(i32),     // notes in the block, by semitones
+    Interpolate,        // command data, from the block's first row to its last
+    ScaleVolume(u16),   // percent
+    Reverse,
+    Rotate(i32),        // rows down, wrapping within the block
+    Humanize(u8),       // nudge command data by up to this much either way
+    OctaveUp,
+    OctaveDown,
+    StepUp,
+    StepDown,
+}
+
+pub struct Editor {
+    seq:    Sequence,
+    row:    usize,
+    col:    usize,      // four per channel, one for each Column
+    edits:  Vec<Edit>,  // made since the last take_edits
+    history: History,
+    step:   Option<Step>,   // being recorded by the current action
+    mark:   Option<(usize, usize)>, // row and channel a block starts from
+    clipboard: Vec<Vec<Field>>,
+    seed:   u32,        // for humanizing
+    octave: u8,         // of the lowest note on the keyboard
+    edit_step: usize,   // rows to move down after entering a note or data
+}
+
+impl Column {
+    // Where the column's characters are within a printed field.
+    pub fn span(&self) -> (usize, usize) {
+        match *self {
+            Column::Note => (0, 3),
+            Column::CommandId => (3, 4),
+            Column::CommandHi => (4, 5),
+            Column::CommandLo => (5, 6),
+        }
+    }
+}
+
+impl Editor {
+    pub fn new(seq: Sequence) -> Self {
+        Editor {
+            seq: seq,
+            row: 0,
+            col: 0,
+            edits: vec![],
+            history: History::new(),
+            step: None,
+            mark: None,
+            clipboard: vec![],
+            seed: 0x2545f491,
+            octave: OCTAVE,
+            edit_step: 1,
+        }
+    }
+    pub fn seq(&self) -> &Sequence { &self.seq }
+    pub fn row(&self) -> usize { self.row }
+    pub fn chan(&self) -> usize { self.col / 4 }
+    pub fn octave(&self) -> u8 { self.octave }
+    pub fn edit_step(&self) -> usize { self.edit_step }
+    pub fn column(&self) -> Column {
+        match self.col % 4 {
+            0 => Column::Note,
+            1 => Column::CommandId,
+            2 => Column::CommandHi,
+            _ => Column::CommandLo,
+        }
+    }
+    // Edits to the pattern since the last call, to be sent to the player.
+    pub fn take_edits(&mut self) -> Vec<Edit> {
+        mem::take(&mut self.edits)
+    }
+
+    pub fn can_undo(&self) -> bool { self.history.can_undo() }
+    pub fn can_redo(&self) -> bool { self.history.can_redo() }
+    // Bound the memory used by the undo history.
+    pub fn set_history_limit(&mut self, bytes: usize) {
+        self.history.set_limit(bytes);
+    }
+
+    pub fn act(&mut self, action: Action) {
+        // with no fields there's no cursor, and nothing to do.
+        if self.seq.is_empty() || self.seq.width() == 0 {
+            return;
+        }
+        let typing = match action {
+            Action::Note(_) | Action::CommandId(_) | Action::Hex(_) =>
+                Some((self.row, self.chan())),
+            _ => None,
+        };
+        self.step = Some(Step::new((self.row, self.col), typing));
+        self.run(action);
+        if let Some(step) = self.step.take() {
+            self.history.push(step);
+        }
+    }
+    pub fn undo(&mut self) {
+        let (edits, cursor) = match self.history.undo() {
+            Some(step) => (step.undo.clone(), step.cursor),
+            None => return,
+        };
+        for edit in edits.into_iter().rev() {
+            self.apply(edit);
+        }
+        self.set_cursor(cursor);
+    }
+    pub fn redo(&mut self) {
+        let (edits, cursor) = match self.history.redo() {
+            Some(step) => (step.edits.clone(), step.cursor),
+            None => return,
+        };
+        for edit in edits {
+            self.apply(edit);
+        }
+        self.set_cursor(cursor);
+    }
+
+    fn run(&mut self, action: Action) {
+        let last = self.seq.len() - 1;
+        match action {
+            Action::Move(dx, dy) => self.move_cursor(dx, dy),
+            Action::NextChannel => self.move_cursor(4 - (self.col % 4) as i32, 0),
+            Action::PrevChannel => self.move_cursor(-4 - (self.col % 4) as i32, 0),
+            Action::PageUp => self.row = self.row.saturating_sub(PAGE),
+            Action::PageDown => self.row = (self.row + PAGE).min(last),
+            Action::Top => self.row = 0,
+            Action::Bottom => self.row = last,
+            Action::InsertRow => {
+                self.row += 1;
+                self.edit(Edit::InsertRow(self.row));
+            }
+            Action::RemoveRow => {
+                self.edit(Edit::RemoveRow(self.row));
+                self.row = self.row.saturating_sub(1);
+            }
+            Action::Clear => {
+                let mut field = self.field();
+                match self.column() {
+                    Column::Note => field.note = Note::Hold,
+                    _ => field.cmd = Command::zero(),
+                }
+                self.set_field(field);
+            }
+            Action::Note(note) => self.enter_note(note),
+            Action::CommandId(id) => self.enter_id(id),
+            Action::Hex(v) => self.enter_hex(v),
+            Action::Undo => {
+                self.step = None;
+                self.undo();
+            }
+            Action::Redo => {
+                self.step = None;
+                self.redo();
+            }
+            Action::Mark => self.toggle_mark(),
+            Action::Copy => self.copy(),
+            Action::Cut => self.cut(),
+            Action::Paste => self.paste(Paste::Over),
+            Action::MixPaste => self.paste(Paste::Mix),
+            Action::PasteFlood => self.paste(Paste::Flood),
+            Action::Transpose(by) => self.transpose(by),
+            Action::Interpolate => self.interpolate(),
+            Action::ScaleVolume(percent) => self.scale_volume(percent),
+            Action::Reverse => self.reverse(),
+            Action::Rotate(by) => self.rotate(by),
+            Action::Humanize(range) => self.humanize(range),
+            Action::OctaveUp => self.octave = (self.octave + 1).min(MAX_OCTAVE),
+            Action::OctaveDown => self.octave = self.octave.saturating_sub(1),
+            Action::StepUp => self.edit_step = (self.edit_step + 1).min(MAX_STEP),
+            Action::StepDown => self.edit_step = self.edit_step.saturating_sub(1),
+        }
+    }
+
+    fn field(&self) -> Field {
+        self.seq.get_field(self.row, self.chan()).clone()
+    }
+    fn set_field(&mut self, field: Field) {
+        let (row, chan) = (self.row, self.chan());
+        self.edit(Edit::SetField(row, chan, field));
+    }
+    // Make an edit, recording how to revert it.
+    fn edit(&mut self, edit: Edit) {
+        let len = self.seq.len();
+        let mut undo = match edit {
+            Edit::SetField(row, col, _) =>
+                vec![Edit::SetField(row, col, self.seq.get_field(row, col).clone())],
+            Edit::InsertRow(row) => vec![Edit::RemoveRow(row.min(len))],
+            Edit::RemoveRow(row) if len > 1 && row < len => {
+                let mut undo = vec![Edit::InsertRow(row)];
+                undo.extend(self.seq.fields[row].iter().enumerate()
+                    .map(|(col, field)| Edit::SetField(row, col, field.clone())));
+                undo
+            }
+            Edit::RemoveRow(_) => return,
+            _ => vec![],
+        };
+        if let Some(ref mut step) = self.step {
+            step.edits.push(edit.clone());
+            undo.reverse();
+            step.undo.append(&mut undo);
+        }
+        self.apply(edit);
+    }
+    fn apply(&mut self, edit: Edit) {
+        match edit {
+            Edit::SetField(row, col, ref field) =>
+                self.seq.set_field(row, col, field.clone()),
+            Edit::InsertRow(row) => self.seq.insert_row(row),
+            Edit::RemoveRow(row) => self.seq.remove_row(row),
+            _ => {},
+        }
+        self.edits.push(edit);
+        self.row = self.row.min(self.seq.len() - 1);
+    }
+    fn set_cursor(&mut self, (row, col): (usize, usize)) {
+        self.row = row.min(self.seq.len() - 1);
+        self.col = col.min(self.seq.width() * 4 - 1);
+    }
+    fn move_cursor(&mut self, dx: i32, dy: i32) {
+        let modulus = |a: i32, b: usize| {
+            let b = b as i32;
+            ((a % b + b) % b) as usize
+        };
+        self.row = modulus(self.row as i32 + dy, self.seq.len());
+        self.col = modulus(self.col as i32 + dx, self.seq.width() * 4);
+    }
+    fn enter_note(&mut self, note: Note) {
+        if self.column() != Column::Note {
+            return;
+        }
+        let mut field = self.field();
+        field.note = match note {
+            Note::Hold => return,
+            note => note + self.octave * 12,
+        };
+        self.set_field(field);
+        self.advance();
+    }
+    fn enter_id(&mut self, id: u8) {
+        if self.column() != Column::CommandId || !COMMANDS.contains(id as char) {
+            return;
+        }
+        let mut field = self.field();
+        field.cmd.data = self.remembered_data(&field, id);
+        field.cmd.id = id;
+        self.set_field(field);
+    }
+    fn enter_hex(&mut self, v: u8) {
+        let mut field = self.field();
+        match self.column() {
+            Column::CommandHi => field.cmd.set_hi(v),
+            Column::CommandLo => field.cmd.set_lo(v),
+            _ => return,
+        }
+        self.set_field(field);
+        // the high digit moves on to the low one; the low digit completes
+        // the data, so go back to the high one on the next row.
+        if self.column() == Column::CommandHi {
+            self.col += 1;
+        } else {
+            self.col -= 1;
+            self.advance();
+        }
+    }
+    // Move down by the edit step, stopping at the last row.
+    fn advance(&mut self) {
+        self.row = (self.row + self.edit_step).min(self.seq.len() - 1);
+    }
+    // Entering a command id with no data takes the data from the last use
+    // of the same command in the channel, unless another command came in
+    // between.
+    fn remembered_data(&self, field: &Field, id: u8) -> u8 {
+        if field.cmd.data != 0 || id == b'0' {
+            return field.cmd.data;
+        }
+        for row in (0..self.row).rev() {
+            let prev = &self.seq.get_field(row, self.chan()).cmd;
+            if prev.id == id {
+                return prev.data;
+            } else if prev.id != b'0' {
+                break;
+            }
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Editor, Action, Column, MAX_OCTAVE};
+    use sequence::{Sequence, Field, Note};
+
+    fn editor(rows: usize, chans: usize) -> Editor {
+        Editor::new(Sequence::new(vec![vec![Field::blank(); chans]; rows]))
+    }
+    fn field(ed: &Editor, row: usize, chan: usize) -> String {
+        ed.seq().get_field(row, chan).to_string()
+    }
+
+    #[test]
+    fn moves_wrap_around() {
+        let mut ed = editor(4, 2);
+        ed.act(Action::Move(0, -1));
+        assert_eq!(ed.row(), 3);
+        ed.act(Action::Move(-1, 0));
+        assert_eq!((ed.chan(), ed.column()), (1, Column::CommandLo));
+        ed.act(Action::NextChannel);
+        assert_eq!((ed.chan(), ed.column()), (0, Column::Note));
+        ed.act(Action::PageDown);
+        assert_eq!(ed.row(), 3);
+    }
+
+    #[test]
+    fn notes_advance_by_edit_step() {
+        let mut ed = editor(4, 1);
+        ed.act(Action::StepUp);
+        assert_eq!(ed.edit_step(), 2);
+        ed.act(Action::Note(Note::On(0)));
+        assert_eq!(ed.row(), 2);
+        ed.act(Action::Note(Note::On(2)));
+        assert_eq!(ed.row(), 3);
+        ed.act(Action::Note(Note::Off));
+        assert_eq!(ed.row(), 3);
+        assert_eq!(field(&ed, 0, 0), "C-4000");
+        assert_eq!(field(&ed, 1, 0), "   000");
+        assert_eq!(field(&ed, 2, 0), "D-4000");
+        assert_eq!(field(&ed, 3, 0), "---000");
+    }
+
+    #[test]
+    fn zero_step_stays_put() {
+        let mut ed = editor(4, 1);
+        ed.act(Action::StepDown);
+        ed.act(Action::StepDown);
+        assert_eq!(ed.edit_step(), 0);
+        ed.act(Action::Note(Note::On(0)));
+        ed.act(Action::Note(Note::On(4)));
+        assert_eq!(ed.row(), 0);
+        assert_eq!(field(&ed, 0, 0), "E-4000");
+    }
+
+    #[test]
+    fn hex_digits_fill_then_advance() {
+        let mut ed = editor(4, 1);
+        ed.act(Action::Move(1, 0));
+        ed.act(Action::CommandId(b'C'));
+        ed.act(Action::Move(1, 0));
+        ed.act(Action::Hex(0x2));
+        assert_eq!((ed.row(), ed.column()), (0, Column::CommandLo));
+        ed.act(Action::Hex(0xa));
+        assert_eq!((ed.row(), ed.column()), (1, Column::CommandHi));
+        assert_eq!(field(&ed, 0, 0), "   C2A");
+    }
+
+    #[test]
+    fn unknown_command_ids_are_ignored() {
+        let mut ed = editor(4, 1);
+        ed.act(Action::Move(1, 0));
+        ed.act(Action::CommandId(b'X'));
+        assert_eq!(field(&ed, 0, 0), "   000");
+        assert!(ed.take_edits().is_empty());
+    }
+
+    #[test]
+    fn octave_stops_at_the_highest_note() {
+        let mut ed = editor(4, 1);
+        for _ in 0..10 {
+            ed.act(Action::OctaveUp);
+        }
+        assert_eq!(ed.octave(), MAX_OCTAVE);
+        ed.act(Action::Note(Note::On(35)));
+        assert_eq!(field(&ed, 0, 0), "B-9000");
+        for _ in 0..10 {
+            ed.act(Action::OctaveDown);
+        }
+        assert_eq!(ed.octave(), 0);
+    }
+
+    #[test]
+    fn rows_insert_and_remove() {
+        let mut ed = editor(2, 1);
+        ed.act(Action::Note(Note::On(0)));
+        ed.act(Action::Top);
+        ed.act(Action::InsertRow);
+        assert_eq!((ed.seq().len(), ed.row()), (3, 1));
+        ed.act(Action::Top);
+        ed.act(Action::RemoveRow);
+        ed.act(Action::RemoveRow);
+        ed.act(Action::RemoveRow);
+        assert_eq!(ed.seq().len(), 1);
+        assert_eq!(ed.take_edits().len(), 4);
+    }
+
+    #[test]
+    fn empty_patterns_ignore_actions() {
+        for seq in [vec![], vec![vec![]; 4]].iter() {
+            let mut ed = Editor::new(Sequence::new(seq.clone()));
+            for action in [Action::Move(1, 1), Action::Bottom, Action::Note(Note::On(0)),
+                    Action::InsertRow, Action::Mark, Action::Paste, Action::Reverse, Action::Undo].iter() {
+                ed.act(action.clone());
+            }
+            assert!(ed.take_edits().is_empty());
+        }
+    }
+}
@@ -0,0 +1,230 @@
+// Operations over the whole of the current block.
+
+use editor::Editor;
+use sequence::{Field, Note, Command, MAX_NOTE};
+use track::Edit;
+
+const FULL_VOL: u8 = 0x40;
+const FULL_GLOBAL_VOL: u8 = 0x80;
+// Commands whose data is an amount, which can stand nudging: slides,
+// volumes, duty, wave position, sends and cutoff. Jumps, instruments,
+// tempo, grooves, arpeggios and packed nibbles are left alone.
+const HUMANIZED: &str = "123CDHPRTVY";
+
+impl Editor {
+    // Rewrite the block with `f`, which gets a copy of its fields by row,
+    // making edits only where they changed.
+    fn rewrite<F: FnOnce(&mut Vec<Vec<Field>>)>(&mut self, f: F) {
+        let b = self.block();
+        let old: Vec<Vec<Field>> = self.seq.fields[b.row..b.row + b.height].iter()
+            .map(|row| row[b.chan..b.chan + b.width].to_vec())
+            .collect();
+        let mut new = old.clone();
+        f(&mut new);
+        for (y, (old, new)) in old.iter().zip(new).enumerate() {
+            for (x, (old, new)) in old.iter().zip(new).enumerate() {
+                if *old != new {
+                    self.edit(Edit::SetField(b.row + y, b.chan + x, new));
+                }
+            }
+        }
+    }
+
+    pub(super) fn transpose(&mut self, semitones: i32) {
+        let by = semitones.unsigned_abs().min(MAX_NOTE as u32) as u8;
+        self.rewrite(|rows| {
+            for field in rows.iter_mut().flat_map(|r| r.iter_mut()) {
+                let note = field.note.clone();
+                field.note = if semitones < 0 { note - by } else { note + by };
+            }
+        });
+    }
+    // Fill in command data on a straight line from the block's first row to
+    // its last, in each channel where both have the same command.
+    pub(super) fn interpolate(&mut self) {
+        self.rewrite(|rows| {
+            let last = rows.len() - 1;
+            if last == 0 {
+                return;
+            }
+            for x in 0..rows[0].len() {
+                let (a, b) = (rows[0][x].cmd.clone(), rows[last][x].cmd.clone());
+                if a.id != b.id {
+                    continue;
+                }
+                for (y, row) in rows.iter_mut().enumerate().take(last).skip(1) {
+                    let data = a.data as i32 + (b.data as i32 - a.data as i32) * y as i32 / last as i32;
+                    row[x].cmd = Command { id: a.id, data: data as u8 };
+                }
+            }
+        });
+    }
+    // Scale note volumes by `percent`: C commands, and the full volume that
+    // notes without a command start at.
+    pub(super) fn scale_volume(&mut self, percent: u16) {
+        let scale = |vol: u8| (vol as u32 * percent as u32 / 100).min(FULL_VOL as u32) as u8;
+        self.rewrite(|rows| {
+            for field in rows.iter_mut().flat_map(|r| r.iter_mut()) {
+                let blank = field.cmd == Command::zero();
+                match field.note {
+                    _ if field.cmd.id == b'C' => field.cmd.data = scale(field.cmd.data),
+                    Note::On(_) if blank && scale(FULL_VOL) != FULL_VOL => {
+                        field.cmd = Command { id: b'C', data: scale(FULL_VOL) };
+                    }
+                    _ => {},
+                }
+            }
+        });
+    }
+    pub(super) fn reverse(&mut self) {
+        self.rewrite(|rows| rows.reverse());
+    }
+    // Move the block's rows down by `by`, wrapping around within it.
+    pub(super) fn rotate(&mut self, by: i32) {
+        self.rewrite(|rows| {
+            let len = rows.len() as i32;
+            let by = ((by % len + len) % len) as usize;
+            rows.rotate_right(by);
+        });
+    }
+    // Nudge command data randomly, by up to `range` either way. Only the
+    // commands in HUMANIZED are touched, and empty ones are left alone.
+    pub(super) fn humanize(&mut self, range: u8) {
+        let mut seed = self.seed;
+        self.rewrite(|rows| {
+            for field in rows.iter_mut().flat_map(|r| r.iter_mut()) {
+                if field.cmd == Command::zero() || !HUMANIZED.contains(field.cmd.id as char) {
+                    continue;
+                }
+                // xorshift
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let span = range as u32 * 2 + 1;
+                let nudge = (seed % span) as i32 - range as i32;
+                let max = match field.cmd.id {
+                    b'C' => FULL_VOL,
+                    b'V' => FULL_GLOBAL_VOL,
+                    _ => 0xff,
+                };
+                field.cmd.data = (field.cmd.data as i32 + nudge).max(0).min(max as i32) as u8;
+            }
+        });
+        self.seed = seed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use editor::{Editor, Action};
+    use file::{parse_rows, write_rows};
+    use sequence::Sequence;
+
+    const PATTERN: &str = "\
+C-4C00 C-4C10
+   000    000
+   000    000
+D-4C40    Y20
+";
+
+    // The whole pattern, marked.
+    fn marked() -> Editor {
+        let mut ed = Editor::new(Sequence::new(parse_rows(PATTERN).unwrap()));
+        ed.act(Action::Mark);
+        ed.act(Action::Move(4, 0));
+        ed.act(Action::Bottom);
+        ed
+    }
+    fn rows(ed: &Editor) -> String {
+        write_rows(&ed.seq().fields)
+    }
+
+    #[test]
+    fn transpose_clamps_notes() {
+        let mut ed = marked();
+        ed.act(Action::Transpose(12));
+        assert_eq!(rows(&ed), "\
+C-5C00 C-5C10
+   000    000
+   000    000
+D-5C40    Y20
+");
+        ed.act(Action::Transpose(-100));
+        assert_eq!(rows(&ed), "\
+C-0C00 C-0C10
+   000    000
+   000    000
+C-0C40    Y20
+");
+        ed.act(Action::Transpose(i32::MAX));
+        assert!(rows(&ed).starts_with("B-9C00 B-9C10"));
+        ed.act(Action::Transpose(i32::MIN));
+        assert!(rows(&ed).starts_with("C-0C00 C-0C10"));
+    }
+
+    #[test]
+    fn interpolate_between_matching_commands() {
+        let mut ed = marked();
+        ed.act(Action::Interpolate);
+        assert_eq!(rows(&ed), "\
+C-4C00 C-4C10
+   C15    000
+   C2A    000
+D-4C40    Y20
+");
+    }
+
+    #[test]
+    fn reverse_and_rotate_within_the_block() {
+        let mut ed = marked();
+        ed.act(Action::Reverse);
+        assert_eq!(rows(&ed), "\
+D-4C40    Y20
+   000    000
+   000    000
+C-4C00 C-4C10
+");
+        ed.act(Action::Rotate(-1));
+        assert_eq!(rows(&ed), "   000    000
+   000    000
+C-4C00 C-4C10
+D-4C40    Y20
+");
+        ed.act(Action::Rotate(6));
+        assert_eq!(rows(&ed), "C-4C00 C-4C10
+D-4C40    Y20
+   000    000
+   000    000
+");
+    }
+
+    #[test]
+    fn scale_volume_adds_commands_to_bare_notes() {
+        let mut ed = Editor::new(Sequence::new(parse_rows("C-4000 D-4C40\n").unwrap()));
+        ed.act(Action::Mark);
+        ed.act(Action::Move(4, 0));
+        ed.act(Action::ScaleVolume(50));
+        assert_eq!(rows(&ed), "C-4C20 D-4C20\n");
+    }
+
+    #[test]
+    fn humanize_leaves_jumps_and_instruments() {
+        let text = "   B04    N01    C3E    Y80\n";
+        let mut ed = Editor::new(Sequence::new(parse_rows(text).unwrap()));
+        ed.act(Action::Mark);
+        ed.act(Action::Move(12, 0));
+        ed.act(Action::Humanize(4));
+        {
+            let row = &ed.seq().fields[0];
+            assert!(row[2].cmd.data >= 0x3a && row[2].cmd.data <= 0x40);
+            assert!(row[3].cmd.data >= 0x7c && row[3].cmd.data <= 0x84);
+        }
+        for _ in 0..20 {
+            ed.act(Action::Humanize(4));
+        }
+        let row = &ed.seq().fields[0];
+        assert_eq!(row[0].to_string(), "   B04");
+        assert_eq!(row[1].to_string(), "   N01");
+        assert!(row[2].cmd.data <= 0x40);
+    }
+}
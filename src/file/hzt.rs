@@ -0,0 +1,354 @@
+// hztrack's own song format: plain text, a header of instrument and groove
+// definitions, then one line per row in the same form as the pattern view.
+//
+//     hztrack 1
+//     groove 6 6
+//     inst pcm 256 0 00030609...
+//     env vol 0:64 8:32 16:0 sustain 1
+//     fadeout 512
+//     pattern
+//     C-5N00    000
+//     ---000 D-5F80
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use mixer::{Source, Osc, NoiseMode, FmPatch, Operator, Adsr};
+use sequence::Field;
+use super::check_commands;
+use track::{Track, Instrument, Envelope};
+
+const FIELD_W: usize = 6;
+
+pub fn save(track: &Track) -> String {
+    let mut out = String::new();
+    writeln!(out, "hztrack 1").unwrap();
+    for groove in &track.grooves {
+        let ticks: Vec<_> = groove.iter().map(|t| t.to_string()).collect();
+        writeln!(out, "groove {}", ticks.join(" ")).unwrap();
+    }
+    for inst in &track.instruments {
+        write_inst(&mut out, track, inst);
+    }
+    writeln!(out, "pattern").unwrap();
+    out.push_str(&write_rows(&track.seq.fields));
+    out
+}
+
+pub fn load(text: &str) -> Result<Track, String> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, "hztrack 1")) => {},
+        _ => return Err("not an hztrack song".to_string()),
+    }
+    let mut grooves = vec![];
+    let mut insts: Vec<Instrument> = vec![];
+    let mut samples: Vec<(usize, Vec<i8>)> = vec![]; // instrument, data
+    let mut body = None;
+    for (num, line) in &mut lines {
+        let err = |msg: &str| format!("line {}: {}", num + 1, msg);
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.first().cloned() {
+            None => {},
+            Some("pattern") => {
+                body = Some(num + 1);
+                break;
+            }
+            Some("groove") => grooves.push(words[1..].iter()
+                .map(|w| w.parse::<u8>().ok().filter(|&t| t > 0))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(err("bad groove"))?),
+            Some("inst") => {
+                let (inst, data) = parse_inst(&words[1..]).ok_or(err("bad instrument"))?;
+                if let Some(data) = data {
+                    samples.push((insts.len(), data));
+                }
+                insts.push(inst);
+            }
+            Some("op") => {
+                let op = parse_op(&words[1..]).ok_or(err("bad operator"))?;
+                match insts.last_mut().map(|i| &mut i.source) {
+                    Some(&mut Source::Fm(ref mut patch)) => Arc::make_mut(patch).ops.push(op),
+                    _ => return Err(err("operator outside an fm instrument")),
+                }
+            }
+            Some("env") => {
+                let inst = insts.last_mut().ok_or(err("envelope outside an instrument"))?;
+                let env = words.get(2..).and_then(parse_env).ok_or(err("bad envelope"))?;
+                match words.get(1).cloned() {
+                    Some("vol") => inst.vol_env = Some(env),
+                    Some("pan") => inst.pan_env = Some(env),
+                    Some("pitch") => inst.pitch_env = Some(env),
+                    Some("filter") => inst.filter_env = Some(env),
+                    _ => return Err(err("unknown envelope")),
+                }
+            }
+            Some("fadeout") => {
+                let inst = insts.last_mut().ok_or(err("fadeout outside an instrument"))?;
+                inst.fadeout = words.get(1).and_then(|w| w.parse().ok())
+                    .ok_or(err("bad fadeout"))?;
+            }
+            Some(word) => return Err(err(&format!("unknown keyword: {}", word))),
+        }
+    }
+    let body = body.ok_or("no pattern".to_string())?;
+    let rows = parse_rows(&text.lines().skip(body).collect::<Vec<_>>().join("\n"))
+        .and_then(|rows| check_commands(&rows).map(|_| rows))
+        .map_err(|e| format!("pattern {}", e))?;
+    if rows.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    let mut track = Track::new(rows);
+    if !grooves.is_empty() {
+        track.grooves = grooves;
+    }
+    for (i, data) in samples {
+        let off = track.add_sample(&data);
+        if let Source::Pcm{off: ref mut o, ..} = insts[i].source {
+            *o = off;
+        }
+    }
+    if !insts.is_empty() {
+        track.instruments = insts;
+    }
+    Ok(track)
+}
+
+// The text form of a block of rows, as used in songs and on the clipboard.
+pub fn write_rows(rows: &[Vec<Field>]) -> String {
+    let mut out = String::new();
+    for row in rows {
+        let fields: Vec<_> = row.iter().map(|f| f.to_string()).collect();
+        out.push_str(&fields.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn parse_rows(text: &str) -> Result<Vec<Vec<Field>>, String> {
+    let mut rows = vec![];
+    for (num, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let err = || format!("row {}: bad field", num + 1);
+        let mut row = vec![];
+        let mut rest = line;
+        loop {
+            if !rest.is_char_boundary(FIELD_W.min(rest.len())) || rest.len() < FIELD_W {
+                return Err(err());
+            }
+            row.push(Field::parse(&rest[..FIELD_W]).ok_or_else(&err)?);
+            rest = &rest[FIELD_W..];
+            if rest.trim().is_empty() {
+                break;
+            }
+            if rest.as_bytes()[0] != b' ' {
+                return Err(err());
+            }
+            rest = &rest[1..];
+        }
+        if rows.first().is_some_and(|r: &Vec<Field>| r.len() != row.len()) {
+            return Err(format!("row {}: wrong number of fields", num + 1));
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+fn write_inst(out: &mut String, track: &Track, inst: &Instrument) {
+    match inst.source {
+        Source::Pcm{off, len, rate, lp} => {
+            let lp = lp.map_or("-".to_string(), |lp| lp.to_string());
+            let data = &track.pcm()[off..off + len as usize];
+            writeln!(out, "inst pcm {} {} {}", rate, lp, to_hex(data)).unwrap();
+        }
+        Source::Osc(Osc::Table(ref tables)) => {
+            let tables: Vec<_> = tables.iter().map(|t| to_hex(t)).collect();
+            writeln!(out, "inst table {}", tables.join(" ")).unwrap();
+        }
+        Source::Osc(ref osc) => {
+            let name = match *osc {
+                Osc::Square => "square",
+                Osc::Saw => "saw",
+                Osc::Triangle => "triangle",
+                Osc::Noise(NoiseMode::Long) => "noise",
+                Osc::Noise(NoiseMode::Short) => "noise-short",
+                Osc::Table(_) => unreachable!(),
+            };
+            writeln!(out, "inst {}", name).unwrap();
+        }
+        Source::Fm(ref patch) => {
+            writeln!(out, "inst fm").unwrap();
+            for op in &patch.ops {
+                let dest = op.dest.map_or("-".to_string(), |d| d.to_string());
+                let env = &op.env;
+                writeln!(out, "op {} {} {} {} {} {} {} {} {}",
+                    op.ratio, op.detune, op.level, op.feedback, dest,
+                    env.attack, env.decay, env.sustain, env.release).unwrap();
+            }
+        }
+    }
+    let envs = [("vol", &inst.vol_env), ("pan", &inst.pan_env),
+        ("pitch", &inst.pitch_env), ("filter", &inst.filter_env)];
+    for &(name, env) in envs.iter() {
+        if let Some(ref env) = *env {
+            write!(out, "env {}", name).unwrap();
+            for &(tick, value) in &env.points {
+                write!(out, " {}:{}", tick, value).unwrap();
+            }
+            if let Some(sus) = env.sustain {
+                write!(out, " sustain {}", sus).unwrap();
+            }
+            if let Some((start, end)) = env.loop_range {
+                write!(out, " loop {} {}", start, end).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+    }
+    if inst.fadeout != 0 {
+        writeln!(out, "fadeout {}", inst.fadeout).unwrap();
+    }
+}
+
+fn parse_inst(words: &[&str]) -> Option<(Instrument, Option<Vec<i8>>)> {
+    let osc = |osc| Some((Instrument::osc(osc), None));
+    match *words.first()? {
+        "pcm" => {
+            let rate = words.get(1)?.parse().ok()?;
+            let lp = match *words.get(2)? {
+                "-" => None,
+                lp => Some(lp.parse().ok()?),
+            };
+            let data = from_hex(words.get(3).cloned().unwrap_or(""))?;
+            let inst = Instrument::pcm(0, data.len() as u32, rate, lp);
+            Some((inst, Some(data)))
+        }
+        "table" => {
+            let tables = words[1..].iter().map(|w| from_hex(w))
+                .collect::<Option<Vec<_>>>()?;
+            osc(Osc::Table(Arc::new(tables)))
+        }
+        "square" => osc(Osc::Square),
+        "saw" => osc(Osc::Saw),
+        "triangle" => osc(Osc::Triangle),
+        "noise" => osc(Osc::Noise(NoiseMode::Long)),
+        "noise-short" => osc(Osc::Noise(NoiseMode::Short)),
+        "fm" => Some((Instrument::fm(FmPatch { ops: vec![] }), None)),
+        _ => None,
+    }
+}
+
+fn parse_op(words: &[&str]) -> Option<Operator> {
+    if words.len() != 9 {
+        return None;
+    }
+    let num = |i: usize| words[i].parse::<f32>().ok();
+    Some(Operator {
+        ratio: num(0)?,
+        detune: num(1)?,
+        level: num(2)?,
+        feedback: num(3)?,
+        dest: match words[4] {
+            "-" => None,
+            dest => Some(dest.parse().ok()?),
+        },
+        env: Adsr {
+            attack: num(5)?,
+            decay: num(6)?,
+            sustain: num(7)?,
+            release: num(8)?,
+        },
+    })
+}
+
+fn parse_env(words: &[&str]) -> Option<Envelope> {
+    let mut env = Envelope {
+        points: vec![],
+        sustain: None,
+        loop_range: None,
+    };
+    let mut words = words.iter();
+    while let Some(&word) = words.next() {
+        match word {
+            "sustain" => env.sustain = Some(words.next()?.parse().ok()?),
+            "loop" => env.loop_range = Some((
+                words.next()?.parse().ok()?,
+                words.next()?.parse().ok()?)),
+            _ => {
+                let mut point = word.splitn(2, ':');
+                let tick = point.next()?.parse().ok()?;
+                let value = point.next()?.parse().ok()?;
+                env.points.push((tick, value));
+            }
+        }
+    }
+    Some(env)
+}
+
+fn to_hex(data: &[i8]) -> String {
+    let mut out = String::with_capacity(data.len() * 2);
+    for &b in data {
+        write!(out, "{:02x}", b as u8).unwrap();
+    }
+    out
+}
+
+fn from_hex(hex: &str) -> Option<Vec<i8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len() / 2)
+        .map(|i| u8::from_str_radix(&hex[i*2..i*2+2], 16).ok().map(|b| b as i8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, save, parse_rows, write_rows};
+    use mixer::Source;
+
+    const ROWS: &str = "C-5N00    000\n---000 D#9FFF\n";
+
+    fn song(body: &str) -> String {
+        format!("hztrack 1\ninst pcm 8363.5 - 00017f80\n{}pattern\n{}", body, ROWS)
+    }
+
+    #[test]
+    fn rows_round_trip() {
+        let rows = parse_rows(ROWS).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), 2);
+        assert_eq!(write_rows(&rows), ROWS);
+        assert!(parse_rows("").unwrap().is_empty());
+        assert_eq!(parse_rows("\n   000\n\n").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn malformed_rows() {
+        for text in &["C-5N0", "C-5N00x   000", "C-5N00\u{e9}  000", "\u{e9}\u{e9}\u{e9}",
+                "H-5000", "C-5N0G", "C-5N00\n   000    000"] {
+            assert!(parse_rows(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn songs_round_trip() {
+        let track = load(&song("env vol 0:64 8:0 sustain 1\nfadeout 512\n")).unwrap();
+        match track.instruments[0].source {
+            Source::Pcm{len, rate, lp, ..} => assert_eq!((len, rate, lp), (4, 8363.5, None)),
+            _ => panic!("not a pcm instrument"),
+        }
+        assert_eq!(save(&load(&save(&track)).unwrap()), save(&track));
+    }
+
+    #[test]
+    fn malformed_songs() {
+        assert!(load("hztrack 2\npattern\n   000\n").is_err());
+        assert!(load("hztrack 1\npattern\n").is_err());
+        assert!(load(&song("env\n")).is_err());
+        assert!(load(&song("env vol 0\n")).is_err());
+        assert!(load(&song("op 1 0 1 0 - 0 0 1 0\n")).is_err());
+        assert!(load("hztrack 1\npattern\n   X00\n").is_err());
+    }
+}
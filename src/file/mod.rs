@@ -0,0 +1,45 @@
+// Reading and writing songs.
+
+use std::fs;
+use std::path::Path;
+
+use sequence::Field;
+use track::{Track, COMMANDS};
+
+mod hzt;
+mod protracker;
+pub use self::hzt::{write_rows, parse_rows};
+
+// Load a song, picking the format by extension: ".mod" for ProTracker
+// modules, and hztrack's own text format otherwise.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Track, String> {
+    let path = path.as_ref();
+    let name = path.display();
+    let data = fs::read(path).map_err(|e| format!("{}: {}", name, e))?;
+    let is_mod = path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mod"));
+    let track = if is_mod {
+        protracker::load(&data)
+    } else {
+        String::from_utf8(data)
+            .map_err(|_| "not a text file".to_string())
+            .and_then(|text| hzt::load(&text))
+    };
+    track.map_err(|e| format!("{}: {}", name, e))
+}
+
+pub fn save<P: AsRef<Path>>(track: &Track, path: P) -> Result<(), String> {
+    let path = path.as_ref();
+    fs::write(path, hzt::save(track))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Songs may only use commands the player knows.
+fn check_commands(rows: &[Vec<Field>]) -> Result<(), String> {
+    for (num, row) in rows.iter().enumerate() {
+        if let Some(field) = row.iter().find(|f| !COMMANDS.contains(f.cmd.id as char)) {
+            return Err(format!("row {}: unknown command id: {}", num + 1, field.cmd.id as char));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+// Import of ProTracker modules.
+//
+// Everything is flattened into one sequence, following the order list.
+// Only effects with an hztrack equivalent are kept, and since each field
+// has one command, a sample change takes the place of the row's effect.
+
+use track::{Track, Instrument};
+use sequence::{Field, Note, Command};
+use super::check_commands;
+
+const SAMPLES: usize = 31;
+const ROWS: usize = 64;
+const C2_PERIOD: f64 = 428.0;   // ProTracker's C-2, played as our C-5
+const C2_NOTE: f64 = 60.0;
+const C2_RATE: f64 = 8363.0;    // sample rate of C-2
+
+struct Sample {
+    len:        usize,
+    finetune:   i8,
+    vol:        u8,
+    lp_start:   usize,
+    lp_len:     usize,
+}
+
+pub fn load(data: &[u8]) -> Result<Track, String> {
+    if data.len() < 1084 {
+        return Err("too short to be a module".to_string());
+    }
+    let width = match &data[1080..1084] {
+        b"M.K." | b"M!K!" | b"FLT4" | b"4CHN" => 4,
+        b"6CHN" => 6,
+        b"8CHN" | b"FLT8" => 8,
+        tag if &tag[2..] == b"CH" => ::std::str::from_utf8(&tag[..2]).ok()
+            .and_then(|n| n.parse().ok())
+            .filter(|&n| n > 0)
+            .ok_or("unknown module type".to_string())?,
+        _ => return Err("unknown module type".to_string()),
+    };
+    let be16 = |off: usize| (data[off] as usize) << 8 | data[off + 1] as usize;
+    let samples: Vec<_> = (0..SAMPLES).map(|i| {
+        let off = 20 + i * 30;
+        Sample {
+            len: be16(off + 22) * 2,
+            finetune: ((data[off + 24] << 4) as i8) >> 4,
+            vol: data[off + 25].min(64),
+            lp_start: be16(off + 26) * 2,
+            lp_len: be16(off + 28) * 2,
+        }
+    }).collect();
+    let song_len = (data[950] as usize).clamp(1, 128);
+    let orders = &data[952..952 + song_len];
+    let patterns = data[952..1080].iter().cloned().max().unwrap_or(0) as usize + 1;
+    let pattern_size = ROWS * width * 4;
+    let mut sample_off = 1084 + patterns * pattern_size;
+
+    let mut rows = vec![];
+    let mut inst = vec![0; width];
+    for (order, &pattern) in orders.iter().enumerate() {
+        let base = 1084 + pattern as usize * pattern_size;
+        if base + pattern_size > data.len() {
+            return Err("pattern data cut short".to_string());
+        }
+        for r in 0..ROWS {
+            let mut row = vec![];
+            let mut page = 0;
+            for c in 0..width {
+                let cell = &data[base + (r * width + c) * 4..][..4];
+                let sample = (cell[0] & 0xf0 | cell[2] >> 4) as usize;
+                let period = ((cell[0] & 0x0f) as usize) << 8 | cell[1] as usize;
+                let (effect, param) = (cell[2] & 0x0f, cell[3]);
+                let note = if period == 0 {
+                    Note::Hold
+                } else {
+                    let n = C2_NOTE + 12.0 * (C2_PERIOD / period as f64).log2();
+                    Note::On(n.round().clamp(0.0, 119.0) as u8)
+                };
+                let cmd = |id, data| Some(Command { id: id, data: data });
+                // the row's high byte goes in a J, placed once the row is done.
+                let mut jump = |row: usize| {
+                    page = row >> 8;
+                    cmd(b'B', row as u8)
+                };
+                let cmd = if sample != 0 && sample != inst[c] {
+                    inst[c] = sample;
+                    cmd(b'N', sample as u8 - 1)
+                } else {
+                    match effect {
+                        0x0 if param != 0 => cmd(b'0', param),
+                        0x1 => cmd(b'1', param),
+                        0x2 => cmd(b'2', param),
+                        0x3 => cmd(b'3', param),
+                        0xB => jump(param as usize % song_len * ROWS),
+                        // pattern break; the row is in decimal, and past the
+                        // end of the pattern it means the first.
+                        0xD => {
+                            let row = (param >> 4) as usize * 10 + (param & 0xf) as usize;
+                            let row = if row < ROWS { row } else { 0 };
+                            jump((order + 1) % song_len * ROWS + row)
+                        }
+                        0xC => cmd(b'C', param.min(0x40)),
+                        0xF if param != 0 && param < 32 => cmd(b'F', param - 1),
+                        0xF if param != 0 => cmd(b'E', param),
+                        _ => None,
+                    }
+                };
+                row.push(Field {
+                    note: note,
+                    cmd: cmd.unwrap_or(Command::zero()),
+                });
+            }
+            if page != 0 {
+                let row_num = rows.len();
+                row.iter_mut().find(|f| is_free(f))
+                    .ok_or(format!("no room for the jump in row {}", row_num))?
+                    .cmd = Command { id: b'J', data: page as u8 };
+            }
+            rows.push(row);
+        }
+    }
+    // the module's initial tempo, wherever there's room for it, unless the
+    // first row sets its own.
+    if !rows[0].iter().any(|f| f.cmd.id == b'E') {
+        if let Some(field) = rows[0].iter_mut().find(|f| is_free(f)) {
+            field.cmd = Command { id: b'E', data: 125 };
+        }
+    }
+
+    check_commands(&rows)?;
+    let mut track = Track::new(rows);
+    track.instruments.clear();
+    for s in &samples {
+        let end = (sample_off + s.len).min(data.len());
+        let pcm: Vec<i8> = data[sample_off.min(end)..end].iter()
+            .map(|&b| (b as i8 as i32 * s.vol as i32 / 64) as i8)
+            .collect();
+        sample_off += s.len;
+        let off = track.add_sample(&pcm);
+        // the mixer plays note 60 at 440 times the instrument's rate.
+        let rate = C2_RATE * 2_f64.powf(s.finetune as f64 / 96.0)
+            / 440.0 * 2_f64.powf((60.0 - C2_NOTE) / 12.0);
+        let (len, lp) = if s.lp_len > 2 {
+            ((s.lp_start + s.lp_len).min(pcm.len()), Some(s.lp_start as u32))
+        } else {
+            (pcm.len(), None)
+        };
+        track.instruments.push(Instrument::pcm(off, len as u32, rate as f32, lp));
+    }
+    Ok(track)
+}
+
+fn is_free(field: &Field) -> bool {
+    field.cmd.id == b'0' && field.cmd.data == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load;
+    use mixer::Source;
+
+    // A module of one pattern, played `orders` times, with two samples: the
+    // first looped, the second at half volume and finetune -1.
+    fn module(tag: &[u8], width: usize, orders: u8, cells: &[(usize, usize, [u8; 4])]) -> Vec<u8> {
+        let mut data = vec![0; 1084 + 64 * width * 4];
+        for &(i, len, finetune, vol, lp_start, lp_len) in &[(0, 8, 0, 64, 2, 4), (1, 4, 0xf, 32, 0, 1)] {
+            let off = 20 + i * 30;
+            data[off + 23] = len;
+            data[off + 24] = finetune;
+            data[off + 25] = vol;
+            data[off + 27] = lp_start;
+            data[off + 29] = lp_len;
+        }
+        data[950] = orders;
+        data[1080..1084].copy_from_slice(tag);
+        for &(row, chan, cell) in cells {
+            let off = 1084 + (row * width + chan) * 4;
+            data[off..off + 4].copy_from_slice(&cell);
+        }
+        data.extend((0..24).map(|i| i as u8 * 2));
+        data
+    }
+
+    fn effect(effect: u8, param: u8) -> [u8; 4] {
+        [0, 0, effect, param]
+    }
+
+    fn field(data: &[u8], row: usize, chan: usize) -> String {
+        load(data).unwrap().seq.get_field(row, chan).to_string()
+    }
+
+    #[test]
+    fn module_types() {
+        for &(tag, width) in &[(b"M.K.", 4), (b"FLT4", 4), (b"6CHN", 6), (b"8CHN", 8), (b"12CH", 12)] {
+            let track = load(&module(tag, width, 1, &[])).unwrap();
+            assert_eq!(track.seq.width(), width);
+            assert_eq!(track.seq.len(), 64);
+        }
+        assert!(load(&module(b"00CH", 4, 1, &[])).is_err());
+        assert!(load(&module(b"XXCH", 4, 1, &[])).is_err());
+        assert!(load(&module(b"M.K!", 4, 1, &[])).is_err());
+        assert!(load(&module(b"M.K.", 4, 1, &[])[..1083]).is_err());
+        assert!(load(&module(b"M.K.", 4, 1, &[])[..1084 + 100]).is_err());
+    }
+
+    #[test]
+    fn samples() {
+        let track = load(&module(b"M.K.", 4, 1, &[])).unwrap();
+        match track.instruments[0].source {
+            Source::Pcm{len, rate, lp, ..} => {
+                assert_eq!((len, lp), (12, Some(4)));
+                assert!((rate as f64 - 8363.0 / 440.0).abs() < 1e-4);
+            }
+            _ => panic!("not a pcm instrument"),
+        }
+        match track.instruments[1].source {
+            Source::Pcm{off, len, rate, lp} => {
+                // a loop of one word is ProTracker's way of not looping.
+                assert_eq!((len, lp), (8, None));
+                assert!((rate as f64 - 8363.0 * 2_f64.powf(-1.0 / 96.0) / 440.0).abs() < 1e-4);
+                assert_eq!(&track.pcm()[off..off + 4], &[16, 17, 18, 19]);
+            }
+            _ => panic!("not a pcm instrument"),
+        }
+        assert_eq!(track.instruments.len(), 31);
+    }
+
+    #[test]
+    fn notes_and_sample_changes() {
+        let data = module(b"M.K.", 4, 1, &[
+            (0, 0, [0x00, 0xd6, 0x1c, 0x20]),   // C-3 with sample 1 and C20
+            (1, 0, [0x01, 0xac, 0x1c, 0x20]),   // C-2, same sample: C20 is kept
+            (2, 0, [0x10, 0x00, 0x00, 0x00]),   // sample 16 alone
+        ]);
+        assert_eq!(field(&data, 0, 0), "C-6N00");
+        assert_eq!(field(&data, 1, 0), "C-5C20");
+        assert_eq!(field(&data, 2, 0), "   N0F");
+    }
+
+    #[test]
+    fn tempo() {
+        // ProTracker's rows last speed * 2.5 / bpm seconds.
+        let seconds = |cells: &[(usize, usize, [u8; 4])]| {
+            load(&module(b"M.K.", 4, 1, cells)).unwrap().analyze().seconds
+        };
+        assert!((seconds(&[]) - 64.0 * 6.0 * 2.5 / 125.0).abs() < 1e-6);
+        assert!((seconds(&[(0, 1, effect(0xf, 3))]) - 64.0 * 3.0 * 2.5 / 125.0).abs() < 1e-6);
+        assert!((seconds(&[(0, 1, effect(0xf, 0x40))]) - 64.0 * 6.0 * 2.5 / 64.0).abs() < 1e-6);
+        assert!((seconds(&[(0, 0, effect(0xf, 0xff)), (32, 3, effect(0xf, 1))])
+            - 32.0 * 6.0 * 2.5 / 255.0 - 32.0 * 2.5 / 255.0).abs() < 1e-6);
+
+        let data = module(b"M.K.", 4, 1, &[(0, 0, effect(0xf, 0x20)), (0, 1, effect(0xf, 2))]);
+        assert_eq!(field(&data, 0, 0), "   E20");
+        assert_eq!(field(&data, 0, 1), "   F01");
+        assert_eq!(field(&data, 0, 2), "   000");
+        let data = module(b"M.K.", 4, 1, &[(0, 1, effect(0xf, 2))]);
+        assert_eq!(field(&data, 0, 0), "   E7D");
+    }
+
+    #[test]
+    fn jumps() {
+        // pattern breaks go to the next order, their row in decimal.
+        let data = module(b"M.K.", 4, 2, &[(10, 0, effect(0xd, 0x12)), (11, 0, effect(0xd, 0x64))]);
+        assert_eq!(field(&data, 10, 0), "   B4C");
+        assert_eq!(field(&data, 11, 0), "   B40");
+        assert_eq!(field(&data, 64 + 10, 0), "   B0C");
+
+        // past row 255, the high byte goes to a free column.
+        let data = module(b"M.K.", 4, 5, &[(0, 1, effect(0xb, 4)), (0, 2, effect(0xc, 0))]);
+        assert_eq!(field(&data, 0, 0), "   J01");
+        assert_eq!(field(&data, 0, 1), "   B00");
+        assert_eq!(field(&data, 0, 3), "   E7D");
+        let analysis = load(&data).unwrap().analyze();
+        assert_eq!((analysis.rows, analysis.loop_row), (2, Some(256)));
+
+        let full: Vec<_> = (1..4).map(|c| (0, c, effect(0xc, 0))).collect();
+        assert!(load(&module(b"M.K.", 4, 5, &[&full[..], &[(0, 0, effect(0xb, 4))]].concat())).is_err());
+        assert!(load(&module(b"M.K.", 4, 5, &[&full[..], &[(0, 0, effect(0xb, 3))]].concat())).is_ok());
+    }
+}
@@ -0,0 +1,439 @@
+// Which keys do what: the piano layout for entering notes, the hex digits,
+// and the keys bound to editor actions and playback. Layouts ship as
+// presets, which a keymap file can pick from and change.
+//
+// A keymap file has one entry per line:
+//
+//     layout azerty               start from a preset layout
+//     note <key> <0..35|off>      semitones above the current octave
+//     hex <key> <0..f>
+//     bind <chord> <what>         e.g. "bind ctrl+z undo"
+//     unbind <chord>
+//
+// Chords are a character or key name, after any of "ctrl+" and "alt+".
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use base32;
+use editor::{Action, Column};
+use sequence::Note;
+
+const DEFAULT: &'static str = include_str!("../res/keymaps/default");
+const LAYOUTS: &'static [(&'static str, &'static str)] = &[
+    ("qwerty", include_str!("../res/keymaps/qwerty")),
+    ("azerty", include_str!("../res/keymaps/azerty")),
+    ("dvorak", include_str!("../res/keymaps/dvorak")),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Insert,
+    Delete,
+    Backspace,
+    Enter,
+    Tab,
+    Esc,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Chord {
+    pub key:    Key,
+    pub ctrl:   bool,
+    pub alt:    bool,
+}
+
+#[derive(Clone)]
+pub enum Bound {
+    Edit(Action),
+    PlaySong,
+    PlayFrom,   // the cursor's row
+    Stop,
+    Pause,      // or resume
+    Save,
+    Quit,
+}
+
+pub struct Keymap {
+    notes:  HashMap<char, Note>,
+    hex:    HashMap<char, u8>,
+    binds:  HashMap<Chord, Bound>,
+}
+
+const KEY_NAMES: &'static [(&'static str, Key)] = &[
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("insert", Key::Insert),
+    ("delete", Key::Delete),
+    ("backspace", Key::Backspace),
+    ("enter", Key::Enter),
+    ("tab", Key::Tab),
+    ("esc", Key::Esc),
+    ("space", Key::Char(' ')),
+];
+
+impl Chord {
+    pub fn key(key: Key) -> Chord {
+        Chord { key: key, ctrl: false, alt: false }
+    }
+    fn parse(raw: &str) -> Result<Chord, String> {
+        let mut chord = Chord::key(Key::Char(' '));
+        let mut rest = raw;
+        loop {
+            if rest.starts_with("ctrl+") && rest.len() > 5 {
+                chord.ctrl = true;
+                rest = &rest[5..];
+            } else if rest.starts_with("alt+") && rest.len() > 4 {
+                chord.alt = true;
+                rest = &rest[4..];
+            } else {
+                break;
+            }
+        }
+        chord.key = match KEY_NAMES.iter().find(|k| k.0 == rest) {
+            Some(&(_, key)) => key,
+            None => Key::Char(single_char(rest)
+                .ok_or_else(|| format!("unknown key: {}", raw))?),
+        };
+        Ok(chord)
+    }
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+fn parse_bound(words: &[&str]) -> Result<Bound, String> {
+    let num = |i: usize| -> Result<i32, String> {
+        words.get(i).and_then(|w| w.parse().ok())
+            .ok_or_else(|| format!("{} needs a number", words[0]))
+    };
+    let edit = |action| Ok(Bound::Edit(action));
+    match words[0] {
+        "play" => Ok(Bound::PlaySong),
+        "play-from" => Ok(Bound::PlayFrom),
+        "stop" => Ok(Bound::Stop),
+        "pause" => Ok(Bound::Pause),
+        "save" => Ok(Bound::Save),
+        "quit" => Ok(Bound::Quit),
+        "move" => edit(Action::Move(num(1)?, num(2)?)),
+        "next-channel" => edit(Action::NextChannel),
+        "prev-channel" => edit(Action::PrevChannel),
+        "page-up" => edit(Action::PageUp),
+        "page-down" => edit(Action::PageDown),
+        "top" => edit(Action::Top),
+        "bottom" => edit(Action::Bottom),
+        "insert-row" => edit(Action::InsertRow),
+        "remove-row" => edit(Action::RemoveRow),
+        "clear" => edit(Action::Clear),
+        "undo" => edit(Action::Undo),
+        "redo" => edit(Action::Redo),
+        "mark" => edit(Action::Mark),
+        "copy" => edit(Action::Copy),
+        "cut" => edit(Action::Cut),
+        "paste" => edit(Action::Paste),
+        "mix-paste" => edit(Action::MixPaste),
+        "paste-flood" => edit(Action::PasteFlood),
+        "transpose" => edit(Action::Transpose(num(1)?)),
+        "interpolate" => edit(Action::Interpolate),
+        "scale-volume" => edit(Action::ScaleVolume(num(1)?.max(0) as u16)),
+        "reverse" => edit(Action::Reverse),
+        "rotate" => edit(Action::Rotate(num(1)?)),
+        "humanize" => edit(Action::Humanize(num(1)?.clamp(0, 0xff) as u8)),
+        "octave-up" => edit(Action::OctaveUp),
+        "octave-down" => edit(Action::OctaveDown),
+        "step-up" => edit(Action::StepUp),
+        "step-down" => edit(Action::StepDown),
+        what => Err(format!("unknown action: {}", what)),
+    }
+}
+
+impl Keymap {
+    fn empty() -> Keymap {
+        Keymap {
+            notes: HashMap::new(),
+            hex: (0..16).flat_map(|v| {
+                let c = ::std::char::from_digit(v, 16).unwrap();
+                vec![(c, v as u8), (c.to_ascii_uppercase(), v as u8)]
+            }).collect(),
+            binds: HashMap::new(),
+        }
+    }
+    pub fn preset(name: &str) -> Result<Keymap, String> {
+        Keymap::parse(&format!("layout {}\n", name))
+    }
+    // Read a keymap file. Its entries go over the default bindings and the
+    // layout it names, or QWERTY.
+    pub fn parse(text: &str) -> Result<Keymap, String> {
+        let layout = text.lines()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>())
+            .find(|w| w.first() == Some(&"layout"))
+            .map_or("qwerty", |w| w.get(1).cloned().unwrap_or(""));
+        let layout = LAYOUTS.iter().find(|l| l.0 == layout)
+            .ok_or_else(|| format!("unknown layout: {}", layout))?.1;
+        let mut keymap = Keymap::empty();
+        keymap.read(DEFAULT).map_err(|e| format!("default keymap: {}", e))?;
+        keymap.read(layout).map_err(|e| format!("layout: {}", e))?;
+        let mut seen = HashMap::new();
+        keymap.read_checked(text, &mut seen)?;
+        keymap.validate()?;
+        Ok(keymap)
+    }
+    fn read(&mut self, text: &str) -> Result<(), String> {
+        self.read_checked(text, &mut HashMap::new())
+    }
+    // Apply entries, failing on any key given twice in the same text.
+    fn read_checked(&mut self, text: &str, seen: &mut HashMap<String, usize>)
+        -> Result<(), String>
+    {
+        for (num, line) in text.lines().enumerate() {
+            let err = |e: String| format!("line {}: {}", num + 1, e);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() || words[0].starts_with('#') {
+                continue;
+            }
+            if words[0] == "layout" {
+                continue;
+            }
+            if words.len() < 2 {
+                return Err(err(format!("{} needs a key", words[0])));
+            }
+            let what = if words[0] == "unbind" { "bind" } else { words[0] };
+            if let Some(prev) = seen.insert(format!("{} {}", what, words[1]), num + 1) {
+                return Err(err(format!("{} also given on line {}", words[1], prev)));
+            }
+            match words[0] {
+                "note" => {
+                    let key = single_char(words[1])
+                        .ok_or_else(|| err(format!("not a single character: {}", words[1])))?;
+                    let note = match words.get(2) {
+                        Some(&"off") => Note::Off,
+                        Some(n) => Note::On(n.parse::<u8>().ok().filter(|&n| n < 36)
+                            .ok_or_else(|| err(format!("bad note: {}", n)))?),
+                        None => return Err(err("note needs a value".to_string())),
+                    };
+                    self.notes.insert(key, note);
+                }
+                "hex" => {
+                    let key = single_char(words[1])
+                        .ok_or_else(|| err(format!("not a single character: {}", words[1])))?;
+                    let digit = words.get(2).and_then(|d| u8::from_str_radix(d, 16).ok())
+                        .filter(|&d| d < 16)
+                        .ok_or_else(|| err("hex needs a digit".to_string()))?;
+                    self.hex.insert(key, digit);
+                }
+                "bind" => {
+                    let chord = Chord::parse(words[1]).map_err(&err)?;
+                    if words.len() < 3 {
+                        return Err(err("bind needs an action".to_string()));
+                    }
+                    let bound = parse_bound(&words[2..]).map_err(&err)?;
+                    self.binds.insert(chord, bound);
+                }
+                "unbind" => {
+                    let chord = Chord::parse(words[1]).map_err(&err)?;
+                    self.binds.remove(&chord);
+                }
+                what => return Err(err(format!("unknown entry: {}", what))),
+            }
+        }
+        Ok(())
+    }
+    // A key that's bound without modifiers can't also enter notes or
+    // digits, as one of the two would never happen. Notes count their
+    // shifted keys too, since those play the same note.
+    fn validate(&self) -> Result<(), String> {
+        let mut conflicts: Vec<String> = self.binds.keys()
+            .filter(|chord| !chord.ctrl && !chord.alt)
+            .filter_map(|chord| match chord.key {
+                Key::Char(c) if self.note(c) != Note::Hold =>
+                    Some(format!("{} is both bound and a note", c)),
+                Key::Char(c) if self.hex.contains_key(&c) =>
+                    Some(format!("{} is both bound and a hex digit", c)),
+                _ => None,
+            })
+            .collect();
+        conflicts.sort();
+        match conflicts.first() {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn bound(&self, chord: &Chord) -> Option<&Bound> {
+        self.binds.get(chord)
+    }
+    pub fn note(&self, c: char) -> Note {
+        self.notes.get(&c)
+            .or_else(|| c.to_lowercase().next().and_then(|l| self.notes.get(&l)))
+            .cloned().unwrap_or(Note::Hold)
+    }
+    pub fn hex(&self, c: char) -> Option<u8> {
+        self.hex.get(&c).cloned()
+    }
+    // What typing `c` does in the editor's current column.
+    pub fn entry(&self, column: Column, c: char) -> Option<Action> {
+        match column {
+            Column::Note => match self.note(c) {
+                Note::Hold => None,
+                note => Some(Action::Note(note)),
+            },
+            Column::CommandId => base32::from_char(c).ok().map(Action::CommandId),
+            Column::CommandHi | Column::CommandLo => self.hex(c).map(Action::Hex),
+        }
+    }
+}
+
+// The keymap to start with: $HZTRACK_KEYMAP, a preset's name or a file, or
+// else the user's keymap file if there is one, or else QWERTY.
+pub fn load() -> Result<Keymap, String> {
+    let path = match env::var("HZTRACK_KEYMAP") {
+        Ok(ref name) if LAYOUTS.iter().any(|l| l.0 == name) => return Keymap::preset(name),
+        Ok(path) => PathBuf::from(path),
+        Err(_) => match config_file() {
+            Some(ref path) if path.exists() => path.clone(),
+            _ => return Keymap::preset("qwerty"),
+        },
+    };
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Keymap::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn config_file() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("hztrack").join("keymap"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Keymap, Chord, Key, Bound, LAYOUTS};
+    use editor::{Action, Column};
+    use sequence::Note;
+
+    fn err(text: &str) -> String {
+        match Keymap::parse(text) {
+            Ok(_) => panic!("accepted: {:?}", text),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn presets_load() {
+        for &(name, _) in LAYOUTS {
+            let keymap = Keymap::preset(name).unwrap();
+            assert!(keymap.notes.values().any(|n| *n == Note::On(0)), "{}", name);
+            assert_eq!(keymap.hex('F'), Some(0xf));
+            match keymap.bound(&Chord::key(Key::Enter)) {
+                Some(&Bound::PlaySong) => {},
+                _ => panic!("enter doesn't play in {}", name),
+            }
+        }
+        assert!(Keymap::preset("colemak").is_err());
+    }
+
+    #[test]
+    fn entries_go_over_the_layout() {
+        let keymap = Keymap::parse("\
+# comment
+layout qwerty
+note z 12
+note ; off
+hex g 0
+unbind enter
+bind ctrl+alt+p play
+").unwrap();
+        assert!(keymap.note('z') == Note::On(12));
+        assert!(keymap.note('Z') == Note::On(12));
+        assert!(keymap.note(';') == Note::Off);
+        assert_eq!(keymap.hex('g'), Some(0));
+        assert!(keymap.bound(&Chord::key(Key::Enter)).is_none());
+        let chord = Chord { key: Key::Char('p'), ctrl: true, alt: true };
+        match keymap.bound(&chord) {
+            Some(&Bound::PlaySong) => {},
+            _ => panic!("ctrl+alt+p isn't bound"),
+        }
+    }
+
+    #[test]
+    fn entry_depends_on_the_column() {
+        let keymap = Keymap::preset("qwerty").unwrap();
+        match keymap.entry(Column::Note, 'z') {
+            Some(Action::Note(Note::On(0))) => {},
+            _ => panic!("z isn't C"),
+        }
+        match keymap.entry(Column::CommandId, 'c') {
+            Some(Action::CommandId(b'C')) => {},
+            _ => panic!("c isn't a command id"),
+        }
+        match keymap.entry(Column::CommandLo, 'a') {
+            Some(Action::Hex(0xa)) => {},
+            _ => panic!("a isn't a digit"),
+        }
+        assert!(keymap.entry(Column::CommandHi, 'z').is_none());
+    }
+
+    #[test]
+    fn duplicates_are_errors() {
+        assert_eq!(err("note z 1\nnote z 2\n"), "line 2: z also given on line 1");
+        assert_eq!(err("bind ctrl+p play\nunbind ctrl+p\n"),
+            "line 2: ctrl+p also given on line 1");
+    }
+
+    #[test]
+    fn conflicts_are_errors() {
+        assert_eq!(err("bind z play\n"), "z is both bound and a note");
+        assert_eq!(err("bind Z play\n"), "Z is both bound and a note");
+        assert_eq!(err("bind f play\n"), "f is both bound and a hex digit");
+        assert!(Keymap::parse("bind alt+z play\nbind ctrl+Z play\n").is_ok());
+    }
+
+    #[test]
+    fn bad_entries_are_errors() {
+        for text in &["layout colemak\n", "note z\n", "note z 36\n", "note zz 1\n",
+                "hex g 10\n", "bind\n", "bind ctrl+p\n", "bind ctrl+p dance\n",
+                "bind ctrl+p transpose\n", "bind ctrl+pp play\n", "frob z\n"] {
+            err(text);
+        }
+    }
+
+    #[test]
+    fn chord_names() {
+        let chord = |ctrl, alt, key| Chord { key: key, ctrl: ctrl, alt: alt };
+        assert!(Chord::parse("p").unwrap() == chord(false, false, Key::Char('p')));
+        assert!(Chord::parse("ctrl+alt+p").unwrap() == chord(true, true, Key::Char('p')));
+        assert!(Chord::parse("alt+ctrl+p").unwrap() == chord(true, true, Key::Char('p')));
+        assert!(Chord::parse("alt+pageup").unwrap() == chord(false, true, Key::PageUp));
+        assert!(Chord::parse("ctrl+space").unwrap() == chord(true, false, Key::Char(' ')));
+        // + is a key like any other.
+        assert!(Chord::parse("ctrl++").unwrap() == chord(true, false, Key::Char('+')));
+        assert!(Chord::parse("+").unwrap() == chord(false, false, Key::Char('+')));
+        assert!(Chord::parse("\u{e9}").unwrap() == chord(false, false, Key::Char('\u{e9}')));
+        for name in &["", "ctrl+", "alt+", "ctrl+alt+", "shift+p", "Ctrl+p", "ctrl-p",
+                "ctrl+pp", "pgup", "ctrl+ p"] {
+            assert_eq!(Chord::parse(name).err(), Some(format!("unknown key: {}", name)));
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// Field names are spelled out and constants carry 'static, as in older Rust.
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+#[cfg(feature = "sdl")]
+extern crate sdl2;
+
+pub mod base32;
+pub mod editor;
+pub mod file;
+pub mod keymap;
+pub mod mixer;
+pub mod player;
+mod queue;
+pub mod render;
+pub mod sequence;
+pub mod track;
@@ -1,11 +1,24 @@
+// Field names are spelled out and constants carry 'static, as in older Rust.
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+extern crate hztrack;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
-mod base32;
-mod mixer;
-mod sequence;
-mod track;
+mod audio;
+mod cli;
+mod session;
+mod term;
+#[cfg(feature = "sdl")]
 mod ui;
 
 fn main() {
-    ui::run();
+    let args: Vec<String> = ::std::env::args().skip(1).collect();
+    #[cfg(feature = "sdl")]
+    {
+        if args.is_empty() {
+            return ui::run();
+        }
+    }
+    ::std::process::exit(cli::main(&args));
 }
@@ -0,0 +1,196 @@
+// Audio outputs that can host a mixer.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use mixer::{Controller, Mixer, Settings};
+use mixer::wav::WavWriter;
+
+pub trait Backend {
+    // Start mixing from `ctrl`; the output runs until the backend is dropped.
+    fn start(&mut self, ctrl: Box<dyn Controller + Send>, settings: &Settings)
+        -> Result<(), String>;
+}
+
+const SRATE: u32 = 48000;
+const BLOCK: usize = 1024; // frames rendered at a time by the paced backends
+
+// Discards everything, but still runs the mixer in real time.
+pub struct NullBackend {
+    thread: Option<Paced>,
+}
+
+// Records everything played into a WAV file.
+pub struct WavBackend {
+    path:   String,
+    thread: Option<Paced>,
+}
+
+// Runs a mixer on its own thread, at the pace of a real sound card.
+struct Paced {
+    stop:   Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl NullBackend {
+    pub fn new() -> Self {
+        NullBackend { thread: None }
+    }
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        NullBackend::new()
+    }
+}
+
+impl Backend for NullBackend {
+    fn start(&mut self, ctrl: Box<dyn Controller + Send>, settings: &Settings)
+        -> Result<(), String>
+    {
+        self.thread = Some(Paced::start(ctrl, *settings, |_| {}));
+        Ok(())
+    }
+}
+
+impl WavBackend {
+    pub fn new(path: &str) -> Self {
+        WavBackend {
+            path: path.to_string(),
+            thread: None,
+        }
+    }
+}
+
+impl Backend for WavBackend {
+    fn start(&mut self, ctrl: Box<dyn Controller + Send>, settings: &Settings)
+        -> Result<(), String>
+    {
+        let mut wav = Some(WavWriter::create(&self.path, SRATE)
+            .map_err(|e| format!("{}: {}", self.path, e))?);
+        self.thread = Some(Paced::start(ctrl, *settings, move |block| {
+            match block {
+                Some(block) => if let Some(ref mut w) = wav {
+                    if let Err(e) = w.write(block) {
+                        eprintln!("error writing wav: {}", e);
+                    }
+                },
+                None => if let Some(w) = wav.take() {
+                    if let Err(e) = w.finish() {
+                        eprintln!("error writing wav: {}", e);
+                    }
+                },
+            }
+        }));
+        Ok(())
+    }
+}
+
+impl Paced {
+    // `sink` gets every rendered block, then None when stopping.
+    fn start<F>(ctrl: Box<dyn Controller + Send>, settings: Settings, mut sink: F) -> Self
+    where F: FnMut(Option<&[i16]>) + Send + 'static
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            let mut mixer = Mixer::new(SRATE as i32, ctrl);
+            mixer.configure(&settings);
+            let mut buf = vec![0; BLOCK * 2];
+            let block_time = Duration::from_secs(BLOCK as u64) / SRATE;
+            let mut due = Instant::now();
+            while !stopped.load(Ordering::Relaxed) {
+                mixer.render(&mut buf);
+                sink(Some(&buf));
+                due += block_time;
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep(due - now);
+                }
+            }
+            sink(None);
+        });
+        Paced {
+            stop: stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Paced {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use super::{Backend, NullBackend, WavBackend, BLOCK};
+    use mixer::{Controller, MixerIn, Settings};
+
+    // Counts the ticks asked of it, with no channels playing.
+    struct Ticks(Arc<AtomicUsize>);
+
+    impl Controller for Ticks {
+        fn next(&mut self) -> MixerIn {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            MixerIn {
+                tick_rate: 6000.0,
+                global_vol: 0x80,
+                pcm: Arc::new(vec![]),
+                chan: vec![],
+            }
+        }
+    }
+
+    fn run(backend: &mut dyn Backend) -> Arc<AtomicUsize> {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        backend.start(Box::new(Ticks(ticks.clone())), &Settings::default()).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        ticks
+    }
+
+    #[test]
+    fn null_backend_runs_until_dropped() {
+        let mut backend = NullBackend::new();
+        let ticks = run(&mut backend);
+        drop(backend);
+        let count = ticks.load(Ordering::Relaxed);
+        // paced in real time: 100ms is 10 ticks, give or take a block.
+        assert!(count > 0 && count < 30, "{} ticks", count);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(ticks.load(Ordering::Relaxed), count);
+    }
+
+    #[test]
+    fn wav_backend_finishes_its_file() {
+        let path = env::temp_dir().join(format!("hztrack-backend-{}.wav", ::std::process::id()));
+        let mut backend = WavBackend::new(path.to_str().unwrap());
+        run(&mut backend);
+        drop(backend);
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let u32at = |off: usize| (0..4).fold(0, |v, i| v | (data[off + i] as usize) << (i * 8));
+        let bytes = data.len() - 44;
+        assert!(bytes > 0 && bytes.is_multiple_of(BLOCK * 4), "{} bytes", bytes);
+        assert_eq!((u32at(4), u32at(24), u32at(40)), (36 + bytes, 48000, bytes));
+    }
+
+    #[test]
+    fn wav_backend_reports_unwritable_paths() {
+        let mut backend = WavBackend::new("/nonexistent/dir/out.wav");
+        let ticks = Arc::new(AtomicUsize::new(0));
+        assert!(backend.start(Box::new(Ticks(ticks)), &Settings::default()).is_err());
+    }
+}
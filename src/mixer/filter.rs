@@ -0,0 +1,88 @@
+// Resonant state-variable filter, run on each voice after resampling.
+
+use std::f32::consts::PI;
+use mixer::{Filter, FilterMode};
+
+#[derive(Clone)]
+pub struct Svf {
+    mode:   FilterMode,
+    a1:     f32,
+    a2:     f32,
+    a3:     f32,
+    k:      f32,    // damping; lower is more resonant
+    ic1:    f32,
+    ic2:    f32,
+}
+
+impl Svf {
+    pub fn new() -> Self {
+        Svf {
+            mode: FilterMode::Off,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            k: 2.0,
+            ic1: 0.0,
+            ic2: 0.0,
+        }
+    }
+    pub fn set(&mut self, filter: &Filter, srate: u32) {
+        if filter.mode == FilterMode::Off {
+            self.mode = FilterMode::Off;
+            self.ic1 = 0.0;
+            self.ic2 = 0.0;
+            return;
+        }
+        // cutoff spans ten octaves up from 20Hz.
+        let freq = 20.0 * 2_f32.powf(filter.cutoff as f32 / 255.0 * 10.0);
+        let freq = freq.min(srate as f32 * 0.45);
+        let g = (PI * freq / srate as f32).tan();
+        self.mode = filter.mode;
+        self.k = 2.0 - 1.95 * filter.reso as f32 / 255.0;
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+    pub fn process(&mut self, x: f32) -> f32 {
+        if self.mode == FilterMode::Off {
+            return x;
+        }
+        let v3 = x - self.ic2;
+        let v1 = self.a1 * self.ic1 + self.a2 * v3;
+        let v2 = self.ic2 + self.a2 * self.ic1 + self.a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+        match self.mode {
+            FilterMode::LowPass => v2,
+            FilterMode::HighPass => x - self.k * v1 - v2,
+            FilterMode::BandPass => v1,
+            FilterMode::Off => x,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Svf;
+    use mixer::{Filter, FilterMode};
+
+    // Level of a steady input after the filter settles: DC, or a tone at
+    // half the sample rate.
+    fn level(mode: FilterMode, cutoff: u8, nyquist: bool) -> f32 {
+        let mut svf = Svf::new();
+        svf.set(&Filter { mode: mode, cutoff: cutoff, reso: 0 }, 44100);
+        let sign = |i: usize| if nyquist && i % 2 == 1 { -1.0 } else { 1.0 };
+        (0..2000).map(|i| svf.process(sign(i)) * sign(i)).last().unwrap()
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(level(FilterMode::Off, 0, true), 1.0);
+        assert!((level(FilterMode::LowPass, 0x80, false) - 1.0).abs() < 0.01);
+        assert!(level(FilterMode::LowPass, 0x80, true).abs() < 0.01);
+        assert!(level(FilterMode::HighPass, 0x80, false).abs() < 0.01);
+        assert!((level(FilterMode::HighPass, 0x80, true) - 1.0).abs() < 0.01);
+        assert!(level(FilterMode::BandPass, 0x80, false).abs() < 0.01);
+        assert!(level(FilterMode::BandPass, 0x80, true).abs() < 0.01);
+    }
+}
@@ -0,0 +1,125 @@
+// Phase-modulation FM voices, in the manner of the OPL/OPN chips.
+
+use std::f32::consts::PI;
+
+pub const MAX_OPS: usize = 4;
+
+#[derive(Clone)]
+pub struct FmPatch {
+    pub ops:        Vec<Operator>,  // two or four; extras are ignored
+}
+
+#[derive(Clone)]
+pub struct Operator {
+    pub ratio:      f32,    // frequency multiple of the note
+    pub detune:     f32,    // cents
+    pub level:      f32,    // 0..1; for a modulator, depth in cycles
+    pub feedback:   f32,    // 0..1, self-modulation
+    pub dest:       Option<usize>, // lower-numbered op to modulate; None = output
+    pub env:        Adsr,
+}
+
+#[derive(Clone, Copy)]
+pub struct Adsr {
+    pub attack:     f32,    // seconds
+    pub decay:      f32,    // seconds
+    pub sustain:    f32,    // 0..1
+    pub release:    f32,    // seconds
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Clone)]
+pub struct FmState {
+    phase:  [f32; MAX_OPS],
+    inc:    [f32; MAX_OPS],
+    level:  [f32; MAX_OPS],
+    stage:  [Stage; MAX_OPS],
+    prev:   [f32; MAX_OPS],     // last output, for feedback
+}
+
+impl FmPatch {
+    // The classic two-op voice: op 1 modulates op 0.
+    pub fn two_op(ratio: f32, depth: f32, feedback: f32) -> Self {
+        let env = Adsr { attack: 0.005, decay: 0.3, sustain: 0.6, release: 0.2 };
+        FmPatch {
+            ops: vec![
+                Operator { ratio: 1.0, detune: 0.0, level: 1.0,
+                    feedback: 0.0, dest: None, env: env },
+                Operator { ratio: ratio, detune: 0.0, level: depth,
+                    feedback: feedback, dest: Some(0), env: env },
+            ],
+        }
+    }
+}
+
+impl FmState {
+    pub fn new() -> Self {
+        FmState {
+            phase: [0.0; MAX_OPS],
+            inc: [0.0; MAX_OPS],
+            level: [0.0; MAX_OPS],
+            stage: [Stage::Attack; MAX_OPS],
+            prev: [0.0; MAX_OPS],
+        }
+    }
+    pub fn set_pitch(&mut self, patch: &FmPatch, freq: f32, srate: u32) {
+        for (inc, op) in self.inc.iter_mut().zip(&patch.ops) {
+            let freq = freq * op.ratio * 2_f32.powf(op.detune / 1200.0);
+            *inc = freq / srate as f32;
+        }
+    }
+    pub fn point(&mut self, patch: &FmPatch, gate: bool, srate: u32) -> f32 {
+        let n = patch.ops.len().min(MAX_OPS);
+        let mut modulation = [0.0; MAX_OPS];
+        let mut out = 0.0;
+        // operators only modulate lower ones, so run from the top down.
+        for i in (0..n).rev() {
+            let op = &patch.ops[i];
+            let env = self.envelope(i, &op.env, gate, srate);
+            let phase = self.phase[i] + modulation[i] + self.prev[i] * op.feedback;
+            let point = (phase * 2.0 * PI).sin() * env * op.level;
+            self.prev[i] = point;
+            self.phase[i] = (self.phase[i] + self.inc[i]).fract();
+            match op.dest {
+                Some(dest) if dest < i => modulation[dest] += point,
+                _ => out += point,
+            }
+        }
+        out
+    }
+    fn envelope(&mut self, i: usize, env: &Adsr, gate: bool, srate: u32) -> f32 {
+        let per_sec = |secs: f32| 1.0 / (secs.max(0.0001) * srate as f32);
+        if !gate {
+            self.stage[i] = Stage::Release;
+        }
+        let level = &mut self.level[i];
+        match self.stage[i] {
+            Stage::Attack => {
+                *level += per_sec(env.attack);
+                if *level >= 1.0 {
+                    *level = 1.0;
+                    self.stage[i] = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                *level -= per_sec(env.decay) * (1.0 - env.sustain);
+                if *level <= env.sustain {
+                    *level = env.sustain;
+                    self.stage[i] = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {},
+            Stage::Release => {
+                *level = (*level - per_sec(env.release)).max(0.0);
+            }
+        }
+        *level
+    }
+}
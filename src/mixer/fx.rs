@@ -0,0 +1,261 @@
+// Master effects, fed by each channel's send amounts.
+
+use std::f32::consts::PI;
+
+pub const SENDS: usize = 3;
+pub const DELAY: usize = 0;
+pub const REVERB: usize = 1;
+pub const CHORUS: usize = 2;
+
+pub struct Fx {
+    pub delay:  Delay,
+    pub reverb: Reverb,
+    pub chorus: Chorus,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DelayTime {
+    Ms(f32),
+    Ticks(u32), // synced to the song's tick length
+}
+
+pub struct Delay {
+    pub time:       DelayTime,
+    pub feedback:   f32,
+    pub level:      f32,
+    line:           Line,
+    len:            usize,
+}
+
+pub struct Reverb {
+    pub size:       f32,    // 0..1, comb feedback
+    pub damp:       f32,    // 0..1, high frequency loss
+    pub level:      f32,
+    combs:          Vec<(Comb, Comb)>,
+    allpasses:      Vec<(Allpass, Allpass)>,
+}
+
+pub struct Chorus {
+    pub rate:       f32,    // Hz
+    pub depth:      f32,    // ms
+    pub level:      f32,
+    line:           Line,
+    lfo:            f32,
+    srate:          u32,
+}
+
+// Stereo delay line.
+struct Line {
+    buf:    Vec<(f32, f32)>,
+    pos:    usize,
+}
+
+struct Comb {
+    buf:    Vec<f32>,
+    pos:    usize,
+    store:  f32,
+}
+
+struct Allpass {
+    buf:    Vec<f32>,
+    pos:    usize,
+}
+
+// Freeverb tunings, in samples at 44.1kHz.
+const COMBS: [usize; 4] = [1116, 1277, 1422, 1557];
+const ALLPASSES: [usize; 2] = [556, 341];
+const SPREAD: usize = 23;
+
+impl Fx {
+    pub fn new(srate: u32) -> Self {
+        Fx {
+            delay: Delay {
+                time: DelayTime::Ticks(3),
+                feedback: 0.4,
+                level: 0.5,
+                line: Line::new(srate as usize * 2),
+                len: 1,
+            },
+            reverb: Reverb::new(srate),
+            chorus: Chorus {
+                rate: 0.8,
+                depth: 4.0,
+                level: 0.7,
+                line: Line::new(srate as usize / 20),
+                lfo: 0.0,
+                srate: srate,
+            },
+        }
+    }
+    // Called on every tick, with that tick's length in samples.
+    pub fn set_tick_len(&mut self, tick_len: f32, srate: u32) {
+        let len = match self.delay.time {
+            DelayTime::Ms(ms) => ms * srate as f32 / 1000.0,
+            DelayTime::Ticks(ticks) => ticks as f32 * tick_len,
+        };
+        self.delay.len = (len as usize).max(1).min(self.delay.line.buf.len() - 1);
+    }
+    pub fn process(&mut self, sends: &[(f32, f32); SENDS]) -> (f32, f32) {
+        let (dl, dr) = self.delay.process(sends[DELAY]);
+        let (rl, rr) = self.reverb.process(sends[REVERB]);
+        let (cl, cr) = self.chorus.process(sends[CHORUS]);
+        (dl + rl + cl, dr + rr + cr)
+    }
+}
+
+impl Delay {
+    fn process(&mut self, (l, r): (f32, f32)) -> (f32, f32) {
+        // tapped before this sample goes in, hence one short.
+        let (wl, wr) = self.line.tap(self.len as f32 - 1.0);
+        // cross the feedback over for a ping-pong echo.
+        self.line.push(l + wr * self.feedback, r + wl * self.feedback);
+        (wl * self.level, wr * self.level)
+    }
+}
+
+impl Reverb {
+    fn new(srate: u32) -> Self {
+        let scale = |len: usize| len * srate as usize / 44100;
+        Reverb {
+            size: 0.8,
+            damp: 0.3,
+            level: 0.3,
+            combs: COMBS.iter().map(|&len|
+                (Comb::new(scale(len)), Comb::new(scale(len + SPREAD))))
+                .collect(),
+            allpasses: ALLPASSES.iter().map(|&len|
+                (Allpass::new(scale(len)), Allpass::new(scale(len + SPREAD))))
+                .collect(),
+        }
+    }
+    fn process(&mut self, (l, r): (f32, f32)) -> (f32, f32) {
+        let input = (l + r) * 0.1;
+        let (mut wl, mut wr) = (0.0, 0.0);
+        for &mut (ref mut cl, ref mut cr) in self.combs.iter_mut() {
+            wl += cl.process(input, self.size, self.damp);
+            wr += cr.process(input, self.size, self.damp);
+        }
+        for &mut (ref mut al, ref mut ar) in self.allpasses.iter_mut() {
+            wl = al.process(wl);
+            wr = ar.process(wr);
+        }
+        (wl * self.level, wr * self.level)
+    }
+}
+
+impl Chorus {
+    fn process(&mut self, (l, r): (f32, f32)) -> (f32, f32) {
+        self.lfo = (self.lfo + self.rate / self.srate as f32).fract();
+        let ms = self.srate as f32 / 1000.0;
+        let center = 10.0 * ms;
+        let swing = self.depth * ms;
+        // left and right sweep a quarter cycle apart.
+        let tl = center + swing * (self.lfo * 2.0 * PI).sin();
+        let tr = center + swing * (self.lfo * 2.0 * PI).cos();
+        self.line.push(l, r);
+        let (wl, _) = self.line.tap(tl);
+        let (_, wr) = self.line.tap(tr);
+        (wl * self.level, wr * self.level)
+    }
+}
+
+impl Line {
+    fn new(len: usize) -> Self {
+        Line {
+            buf: vec![(0.0, 0.0); len.max(2)],
+            pos: 0,
+        }
+    }
+    fn push(&mut self, l: f32, r: f32) {
+        self.pos = (self.pos + 1) % self.buf.len();
+        self.buf[self.pos] = (l, r);
+    }
+    // Read `delay` samples back, interpolating between samples.
+    fn tap(&self, delay: f32) -> (f32, f32) {
+        let len = self.buf.len();
+        let delay = delay.max(0.0).min((len - 2) as f32);
+        let whole = delay as usize;
+        let frac = delay - whole as f32;
+        let a = self.buf[(self.pos + len - whole) % len];
+        let b = self.buf[(self.pos + len - whole - 1) % len];
+        (a.0 + (b.0 - a.0) * frac, a.1 + (b.1 - a.1) * frac)
+    }
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Comb {
+            buf: vec![0.0; len.max(1)],
+            pos: 0,
+            store: 0.0,
+        }
+    }
+    fn process(&mut self, input: f32, feedback: f32, damp: f32) -> f32 {
+        let out = self.buf[self.pos];
+        self.store = out * (1.0 - damp) + self.store * damp;
+        self.buf[self.pos] = input + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buf.len();
+        out
+    }
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Allpass {
+            buf: vec![0.0; len.max(1)],
+            pos: 0,
+        }
+    }
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buf[self.pos];
+        self.buf[self.pos] = input + delayed * 0.5;
+        self.pos = (self.pos + 1) % self.buf.len();
+        delayed - input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fx, DelayTime, SENDS, DELAY, REVERB};
+
+    // Output for an impulse on one send, then silence.
+    fn impulse(fx: &mut Fx, send: usize, len: usize) -> Vec<(f32, f32)> {
+        (0..len).map(|i| {
+            let mut sends = [(0.0, 0.0); SENDS];
+            if i == 0 {
+                sends[send] = (1.0, 0.0);
+            }
+            fx.process(&sends)
+        }).collect()
+    }
+
+    #[test]
+    fn delay_echoes_ping_pong() {
+        let mut fx = Fx::new(1000);
+        fx.delay.time = DelayTime::Ms(10.0);
+        fx.set_tick_len(100.0, 1000);
+        let echoes: Vec<_> = impulse(&mut fx, DELAY, 40).into_iter().enumerate()
+            .filter(|&(_, out)| out != (0.0, 0.0))
+            .collect();
+        assert_eq!(echoes.len(), 3);
+        assert!(echoes[0] == (10, (0.5, 0.0)));
+        assert!(echoes[1] == (20, (0.0, 0.5 * 0.4)));
+        assert_eq!(echoes[2].0, 30);
+
+        // synced to the tick, three ticks by default.
+        let mut fx = Fx::new(1000);
+        fx.set_tick_len(5.0, 1000);
+        assert!(impulse(&mut fx, DELAY, 16)[15] == (0.5, 0.0));
+    }
+
+    #[test]
+    fn reverb_decays() {
+        let mut fx = Fx::new(8000);
+        let out = impulse(&mut fx, REVERB, 8000 * 4);
+        let peak = |from: usize, to: usize| out[from..to].iter()
+            .fold(0.0_f32, |p, o| p.max(o.0.abs()).max(o.1.abs()));
+        assert!(peak(0, 100) == 0.0);
+        assert!(peak(0, 4000) > 0.0);
+        assert!(peak(24000, 32000) < peak(0, 8000) / 10.0);
+    }
+}
@@ -0,0 +1,87 @@
+// Final stage of the mixing bus, between the channel sum and the output.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Clip {
+    Hard,
+    Soft,   // tanh saturation
+    Limit,  // peak limiter: instant attack, slow release
+}
+
+pub struct Master {
+    pub gain:   f32,
+    pub clip:   Clip,
+    env:        f32,    // limiter gain reduction
+    release:    f32,    // per-sample limiter recovery factor
+}
+
+impl Master {
+    pub fn new(srate: u32) -> Self {
+        Master {
+            gain: 0.25,
+            clip: Clip::Soft,
+            env: 1.0,
+            // recover from gain reduction in about 100ms
+            release: (-1.0 / (srate as f32 * 0.1)).exp(),
+        }
+    }
+    pub fn process(&mut self, l: f32, r: f32) -> (f32, f32) {
+        let (l, r) = (l * self.gain, r * self.gain);
+        match self.clip {
+            Clip::Hard => (l.clamp(-1.0, 1.0), r.clamp(-1.0, 1.0)),
+            Clip::Soft => (l.tanh(), r.tanh()),
+            Clip::Limit => {
+                // both sides share one gain, so the stereo image holds.
+                // recover first, so that a held peak can't creep past 1.
+                let peak = l.abs().max(r.abs());
+                self.env = 1.0 - (1.0 - self.env) * self.release;
+                if peak * self.env > 1.0 {
+                    self.env = 1.0 / peak;
+                }
+                (l * self.env, r * self.env)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Master, Clip};
+
+    // Output over a loud burst, then a quiet tail.
+    fn run(clip: Clip) -> Vec<(f32, f32)> {
+        let mut master = Master::new(1000);
+        master.gain = 1.0;
+        master.clip = clip;
+        (0..400).map(|i| if i < 100 { (8.0, -3.0) } else { (0.5, -0.5) })
+            .map(|(l, r)| master.process(l, r))
+            .collect()
+    }
+
+    #[test]
+    fn output_stays_in_bounds() {
+        for &clip in &[Clip::Hard, Clip::Soft, Clip::Limit] {
+            for &(l, r) in &run(clip) {
+                assert!(l.abs() <= 1.0 && r.abs() <= 1.0, "{:?}: {} {}", clip, l, r);
+            }
+        }
+        assert!(run(Clip::Hard)[0] == (1.0, -1.0));
+        assert!(run(Clip::Soft)[0].0 > 0.99);
+    }
+
+    #[test]
+    fn limiter_keeps_the_stereo_image_and_recovers() {
+        let out = run(Clip::Limit);
+        assert!((out[0].0 - 1.0).abs() < 1e-6 && (out[0].1 + 3.0 / 8.0).abs() < 1e-6);
+        // about 100ms to recover, so after 300 it's back to almost unity.
+        assert!(out[100].0 < 0.1);
+        assert!((out[399].0 - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn gain() {
+        let mut master = Master::new(1000);
+        master.gain = 0.5;
+        master.clip = Clip::Hard;
+        assert!(master.process(0.5, -1.0) == (0.25, -0.5));
+    }
+}
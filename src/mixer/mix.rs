@@ -1,78 +1,384 @@
 use mixer::*;
+use mixer::filter::Svf;
+use mixer::fm::FmState;
+use mixer::fx::Fx;
+use mixer::master::Master;
+use mixer::osc::OscState;
+use std::mem;
 use std::num::Wrapping;
 
-const PBITS: u32 = 8; // Bits of fixed-point precision for phase.
+const PBITS: u32 = 16; // Bits of fixed-point precision for phase.
+const OSC_LEN: u32 = 256; // Oscillator cycle length, in samples at pcm_rate.
 
 pub struct Mixer<C> {
     srate:      u32,
     samp_count: Wrapping<u32>, // sample count; used for ticking
     next_tick:  Wrapping<u32>, // will tick again when sample count reaches this
+    tick_frac:  f64,           // part of a sample left over from the last tick
     chan:       Vec<Channel>,
+    peaks:      Vec<f32>,      // per-channel peak output over this tick
     ctrl:       C,
     input:      MixerIn,
+    master:     Master,
+    fx:         Fx,
+    ramp:       f32,           // largest volume change per sample
+}
+
+// How the mix sounds, apart from the song: what the host of a mixer
+// passes on to it.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub gain:           f32,
+    pub clip:           Clip,
+    pub ramp:           f32,    // ms for a full-scale volume change; 0 = none
+    pub delay_time:     DelayTime,
+    pub delay_feedback: f32,
+    pub reverb_size:    f32,
+    pub reverb_damp:    f32,
+    pub chorus_rate:    f32,    // Hz
+    pub chorus_depth:   f32,    // ms
+    pub levels:         [f32; SENDS], // delay, reverb and chorus returns
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            gain: 0.25,
+            clip: Clip::Soft,
+            ramp: 2.0,
+            delay_time: DelayTime::Ticks(3),
+            delay_feedback: 0.4,
+            reverb_size: 0.8,
+            reverb_damp: 0.3,
+            chorus_rate: 0.8,
+            chorus_depth: 4.0,
+            levels: [0.5, 0.3, 0.7],
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Channel {
-    phase:      u32,
-    phase_inc:  u32,
+    voice:      Voice,
+    tail:       Option<Voice>, // previous voice, fading out after a retrigger
+    sends:      [f32; SENDS],
+}
+
+#[derive(Clone)]
+struct Voice {
+    phase:      u64,
+    phase_inc:  u64,
+    source:     Source,
+    osc:        OscState,
+    fm:         FmState,
+    gate:       bool,
+    srate:      u32,
+    duty:       u8,
+    wave_pos:   u8,
+    vol:        f32,    // current volume
+    target:     f32,    // volume to ramp towards
+    pan:        f32,    // -1 (left) ..1 (right)
+    filter:     Svf,
 }
+
 impl Channel {
     fn new() -> Self {
         Channel {
-            phase: 0,
-            phase_inc: 0,
+            voice: Voice::new(),
+            tail: None,
+            sends: [0.0; SENDS],
+        }
+    }
+    fn update(&mut self, inchan: &ChannelIn, srate: u32) {
+        if inchan.trig || !inchan.source.same(&self.voice.source) {
+            let mut old = mem::replace(&mut self.voice, Voice::new());
+            if old.vol > 0.0 {
+                old.target = 0.0;
+                self.tail = Some(old);
+            }
+        }
+        for (send, &amount) in self.sends.iter_mut().zip(&inchan.sends) {
+            *send = amount as f32 / 255.0;
+        }
+        let voice = &mut self.voice;
+        let pbitsf = (1<<PBITS) as f64;
+        let fnote = inchan.note as f64 / 2_f64.powi(8);
+        let pitch = (2_f64).powf((fnote - 60.0) / 12.0) * 440.0;
+        let rate = match inchan.source {
+            Source::Pcm{rate, ..} => rate,
+            Source::Osc(_) => OSC_LEN as f32,
+            Source::Fm(ref patch) => {
+                voice.fm.set_pitch(patch, pitch as f32, srate);
+                0.0
+            }
+        };
+        voice.phase_inc = (pitch * pbitsf * rate as f64 / srate as f64).round() as u64;
+        voice.gate = inchan.gate;
+        voice.srate = srate;
+        voice.source = inchan.source.clone();
+        voice.duty = inchan.duty;
+        voice.wave_pos = inchan.wave_pos;
+        voice.target = inchan.vol as f32 / 64.0 * inchan.mix_vol as f32 / 64.0;
+        voice.pan = inchan.pan as f32 / 64.0;
+        voice.filter.set(&inchan.filter, srate);
+    }
+    fn point(&mut self, pcm: &[i8], ramp: f32) -> (f32, f32) {
+        let (mut l, mut r) = self.voice.point(pcm, ramp);
+        if let Some(mut tail) = self.tail.take() {
+            let (tl, tr) = tail.point(pcm, ramp);
+            l += tl;
+            r += tr;
+            if tail.vol > 0.0 {
+                self.tail = Some(tail);
+            }
         }
+        (l, r)
     }
 }
 
-impl<C: Controller + Send> AudioCallback for Mixer<C> {
-    type Channel = i16;
-    fn callback(&mut self, out: &mut [i16]) {
-        for v in out.iter_mut() {
-            *v = self.get_point()
+impl Voice {
+    fn new() -> Self {
+        Voice {
+            phase: 0,
+            phase_inc: 0,
+            source: Source::Pcm{off: 0, len: 1, rate: 0.0, lp: None},
+            osc: OscState::new(),
+            fm: FmState::new(),
+            gate: true,
+            srate: 1,
+            duty: 0x80,
+            wave_pos: 0,
+            vol: 0.0,
+            target: 0.0,
+            pan: 0.0,
+            filter: Svf::new(),
         }
     }
+    fn point(&mut self, pcm: &[i8], ramp: f32) -> (f32, f32) {
+        self.vol += (self.target - self.vol).max(-ramp).min(ramp);
+        let point = match self.source {
+            Source::Pcm{off, len, lp, ..} => {
+                let end = (len as u64)<<PBITS;
+                match lp {
+                    _ if self.phase < end => {},
+                    Some(start) if start < len => {
+                        let start = (start as u64)<<PBITS;
+                        self.phase = start + (self.phase - end) % (end - start);
+                    }
+                    _ => {
+                        self.phase = end;
+                        self.phase_inc = 0;
+                    }
+                }
+                match pcm.get(off + (self.phase>>PBITS) as usize) {
+                    Some(&point) if self.phase < end => point as f32 / 128.0,
+                    _ => 0.0,
+                }
+            }
+            Source::Osc(ref osc) => {
+                self.phase %= (OSC_LEN as u64)<<PBITS;
+                let t = self.phase as f32 / ((OSC_LEN as u64)<<PBITS) as f32;
+                osc.point(t, self.duty, self.wave_pos, &mut self.osc)
+            }
+            Source::Fm(ref patch) => self.fm.point(patch, self.gate, self.srate),
+        };
+        self.phase  += self.phase_inc;
+        let point   = self.filter.process(point) * self.vol;
+        // balance law: the center is at full volume on both sides.
+        (point * (1.0 - self.pan).min(1.0), point * (1.0 + self.pan).min(1.0))
+    }
 }
 
 impl<C: Controller> Mixer<C> {
     pub fn new(srate: i32, ctrl: C) -> Mixer<C> {
-        Mixer {
+        let mut mixer = Mixer {
             srate:      srate as u32,
             samp_count: Wrapping(0),
             next_tick:  Wrapping(0),
+            tick_frac:  0.0,
             ctrl:       ctrl,
             chan:       vec![],
+            peaks:      vec![],
+            master:     Master::new(srate as u32),
+            fx:         Fx::new(srate as u32),
+            ramp:       1.0,
             input: MixerIn {
-                tick_rate:  0,
+                tick_rate:  0.0,
+                global_vol: 0x80,
                 pcm:        Arc::new(vec![]),
                 chan:       vec![],
             }
+        };
+        mixer.configure(&Settings::default());
+        mixer
+    }
+    pub fn configure(&mut self, settings: &Settings) {
+        self.set_gain(settings.gain);
+        self.set_clip(settings.clip);
+        self.set_ramp(settings.ramp);
+        let fx = &mut self.fx;
+        fx.delay.time = settings.delay_time;
+        fx.delay.feedback = settings.delay_feedback;
+        fx.reverb.size = settings.reverb_size;
+        fx.reverb.damp = settings.reverb_damp;
+        fx.chorus.rate = settings.chorus_rate;
+        fx.chorus.depth = settings.chorus_depth;
+        fx.delay.level = settings.levels[DELAY];
+        fx.reverb.level = settings.levels[REVERB];
+        fx.chorus.level = settings.levels[CHORUS];
+    }
+    // Fill `out` with interleaved stereo samples.
+    pub fn render(&mut self, out: &mut [i16]) {
+        for frame in out.chunks_mut(2) {
+            let (l, r) = self.get_point();
+            frame[0] = l;
+            if frame.len() > 1 {
+                frame[1] = r;
+            }
         }
     }
-    fn get_point(&mut self) -> i16 {
+    fn get_point(&mut self) -> (i16, i16) {
         if self.samp_count == self.next_tick {
-            for (chan, inchan) in self.chan.iter_mut().zip(&mut self.input.chan) {
-                let pbitsf = (1<<PBITS) as f64;
-                let fnote = inchan.note as f64 / 2_f64.powi(8);
-                let pitch = (2_f64).powf((fnote - 60.0) / 12.0) * 440.0;
-                chan.phase_inc =
-                    (pitch * pbitsf * inchan.pcm_rate as f64) as u32 / self.srate;
-            }
+            self.ctrl.levels(&self.peaks);
             self.input = self.ctrl.next();
             self.chan.resize(self.input.chan.len(), Channel::new());
-            let tick_len = self.srate * 60 / self.input.tick_rate as u32;
-            self.next_tick += Wrapping(tick_len);
+            self.peaks.clear();
+            self.peaks.resize(self.chan.len(), 0.0);
+            for (chan, inchan) in self.chan.iter_mut().zip(&self.input.chan) {
+                chan.update(inchan, self.srate);
+            }
+            // carry the fraction over, so that ticks stay in time overall.
+            let tick_len = self.srate as f64 * 60.0 / self.input.tick_rate;
+            let samples = (tick_len + self.tick_frac).floor().max(1.0);
+            self.tick_frac += tick_len - samples;
+            self.next_tick += Wrapping(samples as u32);
+            self.fx.set_tick_len(tick_len as f32, self.srate);
         }
-        let mut total = 0i16;
-        for (chan, inchan) in self.chan.iter_mut().zip(&mut self.input.chan) {
-            chan.phase  = chan.phase % (inchan.pcm_len<<PBITS);
-            let pcm_off = inchan.pcm_off + (chan.phase>>PBITS) as usize;
-            let point   = self.input.pcm[pcm_off];
-            chan.phase  += chan.phase_inc;
-            total = total.saturating_add(point as i16 * inchan.vol)
+        let (mut l, mut r) = (0.0, 0.0);
+        let mut sends = [(0.0, 0.0); SENDS];
+        for (chan, peak) in self.chan.iter_mut().zip(self.peaks.iter_mut()) {
+            let (cl, cr) = chan.point(&self.input.pcm, self.ramp);
+            *peak = peak.max(cl.abs()).max(cr.abs());
+            l += cl;
+            r += cr;
+            for (send, amount) in sends.iter_mut().zip(&chan.sends) {
+                send.0 += cl * amount;
+                send.1 += cr * amount;
+            }
         }
+        let (fl, fr) = self.fx.process(&sends);
+        l += fl;
+        r += fr;
         self.samp_count += Wrapping(1);
-        total
+        let global = self.input.global_vol as f32 / 128.0;
+        let (l, r) = self.master.process(l * global, r * global);
+        ((l * 32767.0) as i16, (r * 32767.0) as i16)
+    }
+    pub fn set_gain(&mut self, gain: f32) {
+        self.master.gain = gain;
+    }
+    pub fn set_clip(&mut self, clip: Clip) {
+        self.master.clip = clip;
+    }
+    pub fn fx(&mut self) -> &mut Fx {
+        &mut self.fx
+    }
+    // Length in milliseconds of a full-scale volume ramp. Zero disables
+    // ramping.
+    pub fn set_ramp(&mut self, ms: f32) {
+        self.ramp = if ms > 0.0 {
+            1000.0 / (self.srate as f32 * ms)
+        } else {
+            1.0
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{Mixer, Channel, PBITS};
+    use mixer::{Controller, MixerIn, ChannelIn, Source, Filter, SENDS};
+
+    fn chan_in(note: u16, source: Source) -> ChannelIn {
+        ChannelIn {
+            note: note,
+            source: source,
+            duty: 0x80,
+            wave_pos: 0,
+            vol: 0x40,
+            pan: 0,
+            mix_vol: 0x40,
+            trig: true,
+            gate: true,
+            filter: Filter::off(),
+            sends: [0; SENDS],
+        }
+    }
+
+    #[test]
+    fn imported_samples_keep_their_pitch() {
+        // ProTracker's C-2 rate with each finetune, as the importer gives it.
+        for finetune in -8..8 {
+            let rate = 8363.0 * 2_f64.powf(finetune as f64 / 96.0) / 440.0;
+            for note in 24..96 {
+                let mut chan = Channel::new();
+                let source = Source::Pcm{off: 0, len: 1, rate: rate as f32, lp: None};
+                chan.update(&chan_in(note << 8, source), 44100);
+                let want = 440.0 * rate * 2_f64.powf((note as f64 - 60.0) / 12.0);
+                let got = chan.voice.phase_inc as f64 / (1u64 << PBITS) as f64 * 44100.0;
+                let cents = 1200.0 * (got / want).log2();
+                assert!(cents.abs() < 1.0, "{} cents off at {} {}", cents, finetune, note);
+            }
+        }
+    }
+
+    // Counts its ticks, at a tempo that isn't a whole number of samples.
+    struct Ticks(usize);
+
+    impl Controller for Ticks {
+        fn next(&mut self) -> MixerIn {
+            self.0 += 1;
+            MixerIn {
+                tick_rate: 120.5 * 6.0,
+                global_vol: 0x80,
+                pcm: Arc::new(vec![]),
+                chan: vec![],
+            }
+        }
+    }
+
+    #[test]
+    fn ticks_keep_time() {
+        // ten minutes at 8kHz, in blocks that don't line up with ticks.
+        let mut mixer = Mixer::new(8000, Ticks(0));
+        let mut buf = vec![0; 1000 * 2];
+        for _ in 0..10 * 60 * 8 {
+            mixer.render(&mut buf);
+        }
+        let want = 10.0 * 120.5 * 6.0;
+        assert!((mixer.ctrl.0 as f64 - want).abs() <= 1.0, "{} ticks", mixer.ctrl.0);
+    }
+
+    #[test]
+    fn volume_ramps() {
+        let pcm = vec![64; 16];
+        let source = Source::Pcm{off: 0, len: 16, rate: 1.0, lp: Some(0)};
+        let mut chan = Channel::new();
+        let play = |chan: &mut Channel, n| -> Vec<f32> {
+            (0..n).map(|_| chan.point(&pcm, 0.25).0).collect()
+        };
+        chan.update(&chan_in(60 << 8, source.clone()), 8000);
+        assert_eq!(play(&mut chan, 5), [0.125, 0.25, 0.375, 0.5, 0.5]);
+        // a retrigger crossfades with the old voice, so the level holds.
+        chan.update(&chan_in(60 << 8, source.clone()), 8000);
+        assert!(chan.tail.is_some());
+        assert_eq!(play(&mut chan, 5), [0.5; 5]);
+        assert!(chan.tail.is_none());
+        let mut cut = chan_in(60 << 8, source);
+        cut.vol = 0;
+        cut.trig = false;
+        chan.update(&cut, 8000);
+        assert_eq!(play(&mut chan, 5), [0.375, 0.25, 0.125, 0.0, 0.0]);
     }
 }
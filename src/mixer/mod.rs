@@ -1,14 +1,29 @@
-use sdl2;
-use sdl2::audio::*;
-
 use std::sync::Arc;
 
+mod backend;
+mod filter;
+mod fm;
+mod fx;
+mod master;
 mod mix;
-use self::mix::*;
+mod osc;
+#[cfg(feature = "sdl")]
+mod sdl;
+pub mod wav;
+pub use self::backend::{Backend, NullBackend, WavBackend};
+pub use self::fm::{FmPatch, Operator, Adsr};
+pub use self::fx::{Fx, Delay, DelayTime, Reverb, Chorus};
+pub use self::fx::{SENDS, DELAY, REVERB, CHORUS};
+pub use self::master::Clip;
+pub use self::mix::{Mixer, Settings};
+pub use self::osc::{Osc, NoiseMode};
+#[cfg(feature = "sdl")]
+pub use self::sdl::SdlBackend;
 
 #[derive(Clone)]
 pub struct MixerIn {
-    pub tick_rate:  u16,    // ticks per minute
+    pub tick_rate:  f64,    // ticks per minute
+    pub global_vol: u8,     // 0x80 = unity
     pub pcm:        Arc<Vec<i8>>,
     pub chan:       Vec<ChannelIn>,
 }
@@ -16,29 +31,80 @@ pub struct MixerIn {
 #[derive(Clone)]
 pub struct ChannelIn {
     pub note:       u16,    // NNTT = 8bit note, 8bit tuning.
-    pub pcm_off:    usize,  // sample offset within data
-    pub pcm_len:    u32,    // sample size
-    pub pcm_rate:   u32,    // per-sample sampling rate
+    pub source:     Source,
+    pub duty:       u8,     // square pulse width, 0x80 = 50%
+    pub wave_pos:   u8,     // position within a wavetable oscillator
     pub vol:        i16,
+    pub pan:        i8,     // -64 (left) ..64 (right)
+    pub mix_vol:    u8,     // user mixing volume, 0x40 = unity
+    pub trig:       bool,   // restart the sample on this tick
+    pub gate:       bool,   // note is held; false starts FM release
+    pub filter:     Filter,
+    pub sends:      [u8; SENDS], // delay, reverb and chorus; 0xff = full
+}
+
+#[derive(Clone)]
+pub enum Source {
+    Pcm {
+        off:    usize,  // sample offset within data
+        len:    u32,    // sample size
+        rate:   f32,    // per-sample sampling rate, fractional for finetune
+        lp:     Option<u32>, // loop start; None plays once
+    },
+    Osc(Osc),
+    Fm(Arc<FmPatch>),
+}
+
+impl Source {
+    // Whether both refer to the same sound, so that changing from one to the
+    // other doesn't need a new voice.
+    pub fn same(&self, other: &Source) -> bool {
+        match (self, other) {
+            (Source::Pcm{off: a, ..}, Source::Pcm{off: b, ..}) => a == b,
+            (Source::Osc(a), Source::Osc(b)) => a.same(b),
+            (Source::Fm(a), Source::Fm(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterMode {
+    Off,
+    LowPass,
+    HighPass,
+    BandPass,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    pub mode:       FilterMode,
+    pub cutoff:     u8,     // 20Hz..20kHz, exponentially
+    pub reso:       u8,
+}
+
+impl Filter {
+    pub fn off() -> Self {
+        Filter {
+            mode: FilterMode::Off,
+            cutoff: 0xff,
+            reso: 0,
+        }
+    }
 }
 
 pub trait Controller {
     fn next(&mut self) -> MixerIn;
+    // Peak output of each channel over the tick just mixed, 1.0 = full
+    // scale; called before each `next`.
+    fn levels(&mut self, _peaks: &[f32]) {}
 }
 
-pub fn run<C: Controller + Send>(sdl: &sdl2::Sdl, ctrl: C) ->
-AudioDevice<Mixer<C>>
-{
-    let audio_subsys = sdl.audio().unwrap();
-    let desired = AudioSpecDesired {
-        freq: Some(48000),
-        channels: Some(1),
-        samples: None,
-    };
-    let device = audio_subsys.open_playback(None, &desired, |spec| {
-        Mixer::new(spec.freq, ctrl)
-    }).unwrap();
-    device.resume();
-    device
+impl<C: Controller + ?Sized> Controller for Box<C> {
+    fn next(&mut self) -> MixerIn {
+        (**self).next()
+    }
+    fn levels(&mut self, peaks: &[f32]) {
+        (**self).levels(peaks)
+    }
 }
-
@@ -0,0 +1,121 @@
+// Oscillators generated on the fly, as an alternative to PCM samples.
+
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub enum Osc {
+    Square,                     // pulse width set by the channel's duty
+    Saw,
+    Triangle,
+    Noise(NoiseMode),
+    Table(Arc<Vec<Vec<i8>>>),   // single-cycle waves, swept by wave_pos
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NoiseMode {
+    Long,   // 15-bit LFSR: white noise
+    Short,  // 7-bit LFSR, repeating every 127 clocks: metallic, pitched noise
+}
+
+const NOISE_CLOCKS: u32 = 32; // LFSR clocks per oscillator cycle
+
+#[derive(Clone)]
+pub struct OscState {
+    lfsr:   u16,
+    clock:  u32,
+}
+
+impl OscState {
+    pub fn new() -> Self {
+        OscState {
+            lfsr: 1,
+            clock: 0,
+        }
+    }
+}
+
+impl Osc {
+    pub fn same(&self, other: &Osc) -> bool {
+        match (self, other) {
+            (Osc::Table(a), Osc::Table(b)) => Arc::ptr_eq(a, b),
+            (Osc::Noise(a), Osc::Noise(b)) => a == b,
+            (Osc::Square, Osc::Square) |
+            (Osc::Saw, Osc::Saw) |
+            (Osc::Triangle, Osc::Triangle) => true,
+            _ => false,
+        }
+    }
+    // One point at phase `t` within the cycle, 0..1.
+    pub fn point(&self, t: f32, duty: u8, wave_pos: u8, st: &mut OscState) -> f32 {
+        match *self {
+            Osc::Square => {
+                let duty = duty.max(1) as f32 / 256.0;
+                if t < duty { 1.0 } else { -1.0 }
+            }
+            Osc::Saw => t * 2.0 - 1.0,
+            Osc::Triangle => 1.0 - (t * 4.0 - 2.0).abs(),
+            Osc::Noise(mode) => {
+                let clock = (t * NOISE_CLOCKS as f32) as u32;
+                if clock != st.clock {
+                    st.clock = clock;
+                    st.lfsr = clock_lfsr(st.lfsr, mode);
+                }
+                if st.lfsr & 1 == 0 { 1.0 } else { -1.0 }
+            }
+            Osc::Table(ref tables) => {
+                if tables.is_empty() {
+                    return 0.0;
+                }
+                // crossfade between the two tables either side of wave_pos.
+                let pos = wave_pos as f32 / 255.0 * (tables.len() - 1) as f32;
+                let (a, b) = (pos as usize, (pos as usize + 1).min(tables.len() - 1));
+                let mix = pos - a as f32;
+                let at = |table: &Vec<i8>| if table.is_empty() {
+                    0.0
+                } else {
+                    table[(t * table.len() as f32) as usize % table.len()] as f32 / 128.0
+                };
+                at(&tables[a]) * (1.0 - mix) + at(&tables[b]) * mix
+            }
+        }
+    }
+}
+
+// Clock the noise LFSR once, as the Game Boy does: the XOR of the low two
+// bits feeds in at bit 14, and in short mode at bit 6 as well, which cuts
+// the sequence down to the low 7 bits.
+fn clock_lfsr(lfsr: u16, mode: NoiseMode) -> u16 {
+    let bit = (lfsr ^ (lfsr >> 1)) & 1;
+    let lfsr = (lfsr >> 1) | (bit << 14);
+    match mode {
+        NoiseMode::Long => lfsr,
+        NoiseMode::Short => lfsr & !(1 << 6) | (bit << 6),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clock_lfsr, NoiseMode};
+
+    fn period(mode: NoiseMode) -> usize {
+        let mask = match mode {
+            NoiseMode::Long => 0x7fff,
+            NoiseMode::Short => 0x7f,
+        };
+        let mut lfsr = clock_lfsr(1, mode);
+        let start = lfsr & mask;
+        let mut n = 1;
+        lfsr = clock_lfsr(lfsr, mode);
+        while lfsr & mask != start {
+            lfsr = clock_lfsr(lfsr, mode);
+            n += 1;
+        }
+        n
+    }
+
+    #[test]
+    fn noise_periods() {
+        assert_eq!(period(NoiseMode::Long), 32767);
+        assert_eq!(period(NoiseMode::Short), 127);
+    }
+}
@@ -0,0 +1,47 @@
+use sdl2;
+use sdl2::audio::*;
+
+use mixer::{Controller, Mixer, Backend, Settings};
+
+pub struct SdlBackend {
+    audio:  sdl2::AudioSubsystem,
+    device: Option<AudioDevice<Callback>>,
+}
+
+struct Callback(Mixer<Box<dyn Controller + Send>>);
+
+impl AudioCallback for Callback {
+    type Channel = i16;
+    fn callback(&mut self, out: &mut [i16]) {
+        self.0.render(out);
+    }
+}
+
+impl SdlBackend {
+    pub fn new(sdl: &sdl2::Sdl) -> Result<Self, String> {
+        Ok(SdlBackend {
+            audio: sdl.audio()?,
+            device: None,
+        })
+    }
+}
+
+impl Backend for SdlBackend {
+    fn start(&mut self, ctrl: Box<dyn Controller + Send>, settings: &Settings)
+        -> Result<(), String>
+    {
+        let desired = AudioSpecDesired {
+            freq: Some(48000),
+            channels: Some(2),
+            samples: None,
+        };
+        let device = self.audio.open_playback(None, &desired, |spec| {
+            let mut mixer = Mixer::new(spec.freq, ctrl);
+            mixer.configure(settings);
+            Callback(mixer)
+        })?;
+        device.resume();
+        self.device = Some(device);
+        Ok(())
+    }
+}
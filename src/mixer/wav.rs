@@ -0,0 +1,89 @@
+// Writes 16-bit stereo PCM as a WAV file.
+
+use std::fs::File;
+use std::io::{self, Write, Seek, SeekFrom, BufWriter};
+use std::path::Path;
+
+pub struct WavWriter {
+    file:   BufWriter<File>,
+    bytes:  u32,    // sample data written so far
+}
+
+impl WavWriter {
+    pub fn create<P: AsRef<Path>>(path: P, srate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        const CHANNELS: u16 = 2;
+        const BITS: u16 = 16;
+        let block = CHANNELS * BITS / 8;
+        file.write_all(b"RIFF")?;
+        file.write_all(&u32le(0))?;     // filled in by finish()
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&u32le(16))?;
+        file.write_all(&u16le(1))?;     // PCM
+        file.write_all(&u16le(CHANNELS))?;
+        file.write_all(&u32le(srate))?;
+        file.write_all(&u32le(srate * block as u32))?;
+        file.write_all(&u16le(block))?;
+        file.write_all(&u16le(BITS))?;
+        file.write_all(b"data")?;
+        file.write_all(&u32le(0))?;     // filled in by finish()
+        Ok(WavWriter {
+            file: file,
+            bytes: 0,
+        })
+    }
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for &s in samples {
+            self.file.write_all(&u16le(s as u16))?;
+        }
+        self.bytes += samples.len() as u32 * 2;
+        Ok(())
+    }
+    // Fill in the chunk sizes, which aren't known until the end.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&u32le(36 + self.bytes))?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&u32le(self.bytes))?;
+        self.file.flush()
+    }
+}
+
+fn u16le(v: u16) -> [u8; 2] {
+    [v as u8, (v >> 8) as u8]
+}
+
+fn u32le(v: u32) -> [u8; 4] {
+    [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use super::WavWriter;
+
+    #[test]
+    fn header_and_samples() {
+        let path = env::temp_dir().join(format!("hztrack-wav-{}.wav", ::std::process::id()));
+        let mut wav = WavWriter::create(&path, 44100).unwrap();
+        wav.write(&[1, -1]).unwrap();
+        wav.write(&[0x1234, -0x8000]).unwrap();
+        wav.finish().unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let u16at = |off: usize| data[off] as u32 | (data[off + 1] as u32) << 8;
+        let u32at = |off: usize| u16at(off) | u16at(off + 2) << 16;
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32at(4), 36 + 8);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!((u32at(16), u16at(20), u16at(22)), (16, 1, 2));
+        assert_eq!((u32at(24), u32at(28)), (44100, 44100 * 4));
+        assert_eq!((u16at(32), u16at(34)), (4, 16));
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32at(40), 8);
+        assert_eq!(&data[44..], &[1, 0, 0xff, 0xff, 0x34, 0x12, 0x00, 0x80]);
+    }
+}
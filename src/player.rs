@@ -0,0 +1,106 @@
+// Hosts a track on the audio thread. The UI never touches the track
+// directly: it sends edits through a queue, and gets back snapshots of the
+// playback state.
+
+use std::collections::VecDeque;
+
+use mixer::{Controller, MixerIn};
+use queue::{self, Sender, Receiver};
+use track::{Track, Transport, Edit};
+
+const QUEUE_LEN: usize = 256;
+pub const METERS: usize = 32;   // channels past this have no meter
+
+// Lives on the audio thread, and owns the track.
+pub struct Player {
+    track:  Track,
+    edits:  Receiver<Edit>,
+    status: Sender<Status>,
+    meters: [u8; METERS],
+}
+
+// Lives on the UI thread.
+pub struct Remote {
+    edits:      Sender<Edit>,
+    pending:    VecDeque<Edit>, // edits that didn't fit in the queue yet
+    status:     Receiver<Status>,
+    last:       Status,
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub row:        usize,
+    pub transport:  Transport,
+    pub paused:     bool,
+    pub meters:     [u8; METERS], // per-channel output peak, 0x40 = full
+}
+
+pub fn new(track: Track) -> (Player, Remote) {
+    let (edit_tx, edit_rx) = queue::channel(QUEUE_LEN);
+    let (status_tx, status_rx) = queue::channel(QUEUE_LEN);
+    let last = Status {
+        row: track.row(),
+        transport: track.transport(),
+        paused: track.paused(),
+        meters: [0; METERS],
+    };
+    let player = Player {
+        track: track,
+        edits: edit_rx,
+        status: status_tx,
+        meters: [0; METERS],
+    };
+    let remote = Remote {
+        edits: edit_tx,
+        pending: VecDeque::new(),
+        status: status_rx,
+        last: last,
+    };
+    (player, remote)
+}
+
+impl Controller for Player {
+    fn next(&mut self) -> MixerIn {
+        while let Some(edit) = self.edits.recv() {
+            self.track.apply(edit);
+        }
+        let out = self.track.next();
+        // if the UI is behind on reading, it just misses this one.
+        let _ = self.status.send(Status {
+            row: self.track.row(),
+            transport: self.track.transport(),
+            paused: self.track.paused(),
+            meters: self.meters,
+        });
+        out
+    }
+    fn levels(&mut self, peaks: &[f32]) {
+        self.meters = [0; METERS];
+        for (meter, &peak) in self.meters.iter_mut().zip(peaks) {
+            *meter = (peak * 64.0).min(64.0) as u8;
+        }
+    }
+}
+
+impl Remote {
+    pub fn send(&mut self, edit: Edit) {
+        self.pending.push_back(edit);
+        self.flush();
+    }
+    // Latest playback state published by the player.
+    pub fn status(&mut self) -> &Status {
+        self.flush();
+        while let Some(status) = self.status.recv() {
+            self.last = status;
+        }
+        &self.last
+    }
+    fn flush(&mut self) {
+        while let Some(edit) = self.pending.pop_front() {
+            if let Err(edit) = self.edits.send(edit) {
+                self.pending.push_front(edit);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Wait-free, bounded, single-producer single-consumer queue. Neither end
+// ever blocks or allocates, so it's safe to use from the audio callback.
+
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Ring<T> {
+    buf:    Vec<UnsafeCell<Option<T>>>,
+    head:   AtomicUsize,    // next slot to read; only the receiver writes it
+    tail:   AtomicUsize,    // next slot to write; only the sender writes it
+}
+
+// Each slot is only touched by one side at a time, as ordered by head/tail.
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+pub struct Sender<T> {
+    ring: Arc<Ring<T>>,
+}
+
+pub struct Receiver<T> {
+    ring: Arc<Ring<T>>,
+}
+
+pub fn channel<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    let ring = Arc::new(Ring {
+        buf: (0..cap.max(1)).map(|_| UnsafeCell::new(None)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (Sender { ring: ring.clone() }, Receiver { ring: ring })
+}
+
+impl<T> Sender<T> {
+    // Hands the item back if the queue is full.
+    pub fn send(&mut self, item: T) -> Result<(), T> {
+        let ring = &self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == ring.buf.len() {
+            return Err(item);
+        }
+        unsafe {
+            *ring.buf[tail % ring.buf.len()].get() = Some(item);
+        }
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn recv(&mut self) -> Option<T> {
+        let ring = &self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let item = unsafe {
+            (*ring.buf[head % ring.buf.len()].get()).take()
+        };
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::channel;
+
+    #[test]
+    fn first_in_first_out() {
+        let (mut tx, mut rx) = channel(4);
+        assert_eq!(rx.recv(), None);
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv(), Some(0));
+        assert_eq!(rx.recv(), Some(1));
+        assert_eq!(rx.recv(), Some(2));
+        assert_eq!(rx.recv(), None);
+    }
+
+    #[test]
+    fn full_hands_items_back() {
+        let (mut tx, mut rx) = channel(2);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(tx.send(3), Err(3));
+        assert_eq!(rx.recv(), Some(1));
+        tx.send(3).unwrap();
+        assert_eq!(rx.recv(), Some(2));
+        assert_eq!(rx.recv(), Some(3));
+        // wrapping around the ring many times over.
+        for i in 0..100 {
+            tx.send(i).unwrap();
+            assert_eq!(rx.recv(), Some(i));
+        }
+    }
+
+    #[test]
+    fn across_threads() {
+        let (mut tx, mut rx) = channel(16);
+        let sender = thread::spawn(move || {
+            for mut i in 0..10000 {
+                while let Err(back) = tx.send(i) {
+                    i = back;
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut next = 0;
+        while next < 10000 {
+            match rx.recv() {
+                Some(i) => {
+                    assert_eq!(i, next);
+                    next += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        sender.join().unwrap();
+        assert_eq!(rx.recv(), None);
+    }
+}
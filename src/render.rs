@@ -0,0 +1,61 @@
+// Offline rendering of a whole song, faster than real time.
+
+use std::path::Path;
+
+use mixer::{Mixer, Settings};
+use mixer::wav::WavWriter;
+use track::Track;
+
+const BLOCK: usize = 4096;
+
+// Render `track` from the top into a WAV file. Without a length, stops
+// where the song ends or first loops. Returns the length rendered.
+pub fn render_wav<P>(mut track: Track, path: P, srate: u32, seconds: Option<f64>,
+                     settings: &Settings)
+    -> Result<f64, String>
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let err = |e: ::std::io::Error| format!("{}: {}", path.display(), e);
+    let seconds = seconds.unwrap_or_else(|| track.analyze().seconds);
+    let mut frames = (seconds * srate as f64).round() as usize;
+    let mut wav = WavWriter::create(path, srate).map_err(&err)?;
+    track.play_song();
+    let mut mixer = Mixer::new(srate as i32, track);
+    mixer.configure(settings);
+    let mut buf = vec![0; BLOCK * 2];
+    while frames > 0 {
+        let len = frames.min(BLOCK);
+        mixer.render(&mut buf[..len * 2]);
+        wav.write(&buf[..len * 2]).map_err(&err)?;
+        frames -= len;
+    }
+    wav.finish().map_err(&err)?;
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use super::render_wav;
+    use file::parse_rows;
+    use mixer::Settings;
+    use track::Track;
+
+    #[test]
+    fn renders_the_whole_song() {
+        let path = env::temp_dir().join(format!("hztrack-render-{}.wav", ::std::process::id()));
+        // 4 rows of 6 ticks at 120 rows per minute: 2 seconds.
+        let track = Track::new(parse_rows("C-5000\n   000\n   000\n   000\n").unwrap());
+        let seconds = render_wav(track.clone(), &path, 8000, None, &Settings::default()).unwrap();
+        assert!((seconds - 2.0).abs() < 1e-9);
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 44 + 16000 * 4);
+        assert!(data[44..].iter().any(|&b| b != 0));
+
+        render_wav(track, &path, 8000, Some(0.5), &Settings::default()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 44 + 4000 * 4);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,23 +1,24 @@
 use std::fmt;
 use base32;
 
+#[derive(Clone)]
 pub struct Sequence {
     // TODO: make private
     pub fields: Vec<Vec<Field>>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Field {
     pub note: Note,
     pub cmd:  Command,
 }
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum Note {
     On(u8),
     Off,
     Hold,
 }
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Command {
     pub id: u8,
     pub data: u8,
@@ -33,11 +34,68 @@ impl Sequence {
         &self.fields[row][col]
     }
     pub fn width(&self) -> usize {
-        self.fields[0].len()
+        self.fields.first().map_or(0, |row| row.len())
     }
     pub fn len(&self) -> usize {
         self.fields.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+    pub fn set_field(&mut self, row: usize, col: usize, field: Field) {
+        self.fields[row][col] = field;
+    }
+    // Insert a blank row before `row`.
+    pub fn insert_row(&mut self, row: usize) {
+        let blank = vec![Field::blank(); self.width()];
+        self.fields.insert(row.min(self.len()), blank);
+    }
+    pub fn remove_row(&mut self, row: usize) {
+        if self.len() > 1 && row < self.len() {
+            self.fields.remove(row);
+        }
+    }
+}
+
+pub const MAX_NOTE: u8 = 119;   // B-9, the highest with a one digit octave
+
+const NOTE_NAME: &'static str = "C-C#D-D#E-F-F#G-G#A-A#B-";
+
+impl Field {
+    pub fn blank() -> Field {
+        Field{note: Note::Hold, cmd: Command::zero()}
+    }
+    pub fn is_empty(&self) -> bool {
+        match self.note {
+            Note::Hold => self.cmd.id == b'0' && self.cmd.data == 0,
+            _ => false,
+        }
+    }
+    // Read back the form written by Display, e.g. "C#4F06" or "   000".
+    pub fn parse(raw: &str) -> Option<Field> {
+        if raw.len() != 6 || !raw.is_char_boundary(3) {
+            return None;
+        }
+        Some(Field {
+            note: Note::parse(&raw[..3])?,
+            cmd: Command::parse(&raw[3..])?,
+        })
+    }
+}
+
+impl Note {
+    pub fn parse(raw: &str) -> Option<Note> {
+        match raw {
+            "---" => Some(Note::Off),
+            "   " => Some(Note::Hold),
+            _ if raw.len() == 3 && raw.is_char_boundary(2) => {
+                let name = (0..12).find(|&i| NOTE_NAME[i*2..i*2+2] == raw[..2])?;
+                let octave = raw[2..].parse::<u8>().ok()?;
+                Some(Note::On(octave * 12 + name as u8))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Field {
@@ -46,11 +104,22 @@ impl fmt::Display for Field {
     }
 }
 
+// Transposing clamps notes to the playable range.
 impl ::std::ops::Add<u8> for Note {
     type Output = Note;
     fn add(self, with: u8) -> Note {
         match self {
-            Note::On(v) => Note::On(v + with),
+            Note::On(v) => Note::On(v.saturating_add(with).min(MAX_NOTE)),
+            _ => self
+        }
+    }
+}
+
+impl ::std::ops::Sub<u8> for Note {
+    type Output = Note;
+    fn sub(self, with: u8) -> Note {
+        match self {
+            Note::On(v) => Note::On(v.saturating_sub(with).min(MAX_NOTE)),
             _ => self
         }
     }
@@ -60,7 +129,6 @@ impl fmt::Display for Note {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Note::On(ref note) => {
-                const NOTE_NAME: &'static str = "C-C#D-D#E-F-F#G-G#A-A#B-";
                 let name = *note as usize % 12;
                 let octave = note / 12;
                 write!(f, "{}{}", &NOTE_NAME[name*2..name*2+2], octave)
@@ -72,7 +140,8 @@ impl fmt::Display for Note {
 }
 
 impl Command {
-    pub fn zero() -> Command { Command { id: '0' as u8, data: 0 } }
+    pub fn zero() -> Command { Command { id: b'0', data: 0 } }
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(raw: &str) -> Command {
         let mut chars = raw.chars();
         Command {
@@ -80,6 +149,18 @@ impl Command {
             data: u8::from_str_radix(chars.as_str(), 16).unwrap(),
         }
     }
+    pub fn parse(raw: &str) -> Option<Command> {
+        let mut chars = raw.chars();
+        let id = base32::from_char(chars.next()?).ok()?;
+        let data = chars.as_str();
+        if data.len() != 2 {
+            return None;
+        }
+        Some(Command {
+            id: id,
+            data: u8::from_str_radix(data, 16).ok()?,
+        })
+    }
     pub fn hi(&self) -> u8 { self.data >> 4 }
     pub fn lo(&self) -> u8 { self.data & 0xf }
     pub fn set_hi(&mut self, v: u8) { self.data = self.lo() + (v << 4) }
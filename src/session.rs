@@ -0,0 +1,55 @@
+// The frontends' side of a playing track: the editor, working on a copy of
+// the pattern, and the remote that its edits go through to the player.
+
+use hztrack::editor::{Editor, Action};
+use hztrack::player::{self, Player, Remote};
+use hztrack::sequence::Field;
+use hztrack::track::{Track, Edit};
+
+// Size of a new song.
+const NEW_WIDTH: usize = 4;
+const NEW_LEN: usize = 64;
+
+pub struct Session {
+    pub editor: Editor,
+    pub remote: Remote,
+    track:      Track,  // all but the pattern, which the editor has
+}
+
+// An empty song, to start from.
+pub fn new_song() -> Track {
+    Track::new(vec![vec![Field::blank(); NEW_WIDTH]; NEW_LEN])
+}
+
+impl Session {
+    pub fn new(track: Track) -> (Session, Player) {
+        let copy = track.clone();
+        let (player, remote) = player::new(track);
+        let session = Session {
+            editor: Editor::new(copy.seq.clone()),
+            remote: remote,
+            track: copy,
+        };
+        (session, player)
+    }
+    // Returns whether the pattern changed.
+    pub fn act(&mut self, action: Action) -> bool {
+        self.editor.act(action);
+        let edits = self.editor.take_edits();
+        let changed = !edits.is_empty();
+        for edit in edits {
+            self.remote.send(edit);
+        }
+        changed
+    }
+    // Transport and mixing changes, which leave the pattern alone.
+    pub fn send(&mut self, edit: Edit) {
+        self.remote.send(edit);
+    }
+    // The song as edited so far.
+    pub fn track(&self) -> Track {
+        let mut track = self.track.clone();
+        track.seq = self.editor.seq().clone();
+        track
+    }
+}
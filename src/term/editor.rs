@@ -0,0 +1,270 @@
+// Edits a song in the terminal, driving the same editor as the SDL window.
+
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use hztrack::file;
+use hztrack::player::Player;
+use hztrack::track::{Track, Edit, Transport};
+use hztrack::keymap::{Keymap, Bound};
+use session::Session;
+use term::{Terminal, Key, inverse, highlight};
+
+const CONTEXT: usize = 7;   // rows shown above and below the cursor
+const FRAME: u64 = 30;      // milliseconds between redraws
+
+// The default bindings; a keymap file may have changed them, so the help
+// says so.
+const HELP: &'static str = "\
+default keys:  enter play  ^r play from row  space pause  esc stop
+ins/del row  ^z undo  ^y redo  ^s save  ^q quit
+^b mark  ^c copy  ^x cut  ^v paste  ^n mix paste  ^f paste flood
+alt: q/a transpose (shift octave)  i interpolate  -/= volume
+     r reverse  t/T rotate  h humanize  ,/. octave  ;/' step";
+
+pub struct Editor {
+    session:    Session,
+    keymap:     Keymap,
+    path:       PathBuf,    // where to save
+    modified:   bool,
+    quitting:   bool,       // asked to quit with unsaved changes
+    message:    String,
+}
+
+impl Editor {
+    pub fn new(mut track: Track, path: PathBuf, keymap: Keymap) -> (Editor, Player) {
+        track.stop();
+        let (session, player) = Session::new(track);
+        let editor = Editor {
+            session: session,
+            keymap: keymap,
+            path: path,
+            modified: false,
+            quitting: false,
+            message: String::new(),
+        };
+        (editor, player)
+    }
+    // Run until the user quits.
+    pub fn run(&mut self, term: &mut Terminal) {
+        loop {
+            for key in term.keys() {
+                if !self.key(key) {
+                    self.session.send(Edit::Stop);
+                    return;
+                }
+            }
+            let lines = self.draw();
+            term.draw(&lines);
+            thread::sleep(Duration::from_millis(FRAME));
+        }
+    }
+    // Handle one key press. Returns false to quit.
+    pub fn key(&mut self, key: Key) -> bool {
+        let quitting = self.quitting;
+        self.quitting = false;
+        self.message.clear();
+        match self.keymap.bound(&key.chord()).cloned() {
+            Some(Bound::Quit) => {
+                if !self.modified || quitting {
+                    return false;
+                }
+                self.quitting = true;
+                self.message = "unsaved changes; quit again to lose them".to_string();
+            }
+            Some(Bound::Save) => self.save(),
+            Some(Bound::PlaySong) => self.session.send(Edit::PlaySong),
+            Some(Bound::PlayFrom) => {
+                let row = self.session.editor.row();
+                self.session.send(Edit::PlayFrom(row));
+            }
+            Some(Bound::Stop) => self.session.send(Edit::Stop),
+            Some(Bound::Pause) => {
+                let edit = if self.session.remote.status().paused {
+                    Edit::Resume
+                } else {
+                    Edit::Pause
+                };
+                self.session.send(edit);
+            }
+            Some(Bound::Edit(action)) => self.modified |= self.session.act(action),
+            None => if let Key::Char(c) = key {
+                let column = self.session.editor.column();
+                if let Some(action) = self.keymap.entry(column, c) {
+                    self.modified |= self.session.act(action);
+                }
+            },
+        }
+        true
+    }
+    fn save(&mut self) {
+        match file::save(&self.session.track(), &self.path) {
+            Ok(()) => {
+                self.modified = false;
+                self.message = format!("saved {}", self.path.display());
+            }
+            Err(e) => self.message = e,
+        }
+    }
+
+    fn draw(&mut self) -> Vec<String> {
+        let status = self.session.remote.status().clone();
+        let state = match (status.transport, status.paused) {
+            (Transport::Stopped, _) => "stopped",
+            (_, true) => "paused",
+            (Transport::Song, _) => "playing",
+            (Transport::Pattern, _) => "looping",
+        };
+        let editor = &self.session.editor;
+        let seq = editor.seq();
+        let mut lines = vec![
+            format!("hztrack  {}{}  row {:3}/{:<3} chan {}/{}  octave {}  step {}  {}",
+                self.path.display(), if self.modified { "*" } else { "" },
+                editor.row(), seq.len(), editor.chan() + 1, seq.width(),
+                editor.octave(), editor.edit_step(), state),
+            self.message.clone(),
+        ];
+        let header: Vec<String> = (0..seq.width())
+            .map(|i| format!("{:<6}", i + 1))
+            .collect();
+        lines.push(format!("     {}", header.join(" ")));
+        let playing = status.transport != Transport::Stopped;
+        let block = if editor.marked() { Some(editor.block()) } else { None };
+        for i in 0..CONTEXT * 2 + 1 {
+            let row = (editor.row() + i).wrapping_sub(CONTEXT);
+            if row >= seq.len() {
+                lines.push(String::new());
+                continue;
+            }
+            let fields: Vec<String> = seq.fields[row].iter()
+                .enumerate()
+                .map(|(chan, field)| {
+                    let text = format!("{}", field);
+                    let mark = |t: &str| match block {
+                        Some(b) if b.contains(row, chan) => highlight(t),
+                        _ => t.to_string(),
+                    };
+                    if row != editor.row() || chan != editor.chan() {
+                        return mark(&text);
+                    }
+                    let (a, b) = editor.column().span();
+                    format!("{}{}{}", mark(&text[..a]), inverse(&text[a..b]), mark(&text[b..]))
+                })
+                .collect();
+            let mark = if playing && row == status.row { '>' } else { ' ' };
+            lines.push(format!("{}{:3}  {}", mark, row, fields.join(" ")));
+        }
+        lines.extend(HELP.lines().map(|l| l.to_string()));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use super::Editor;
+    use hztrack::file::{parse_rows, write_rows};
+    use hztrack::keymap::Keymap;
+    use hztrack::mixer::Controller;
+    use hztrack::player::Player;
+    use hztrack::track::{Track, Transport};
+    use term::Key;
+
+    fn editor(path: PathBuf) -> (Editor, Player) {
+        let track = Track::new(parse_rows("   000    000\n   000    000\n   000    000\n").unwrap());
+        Editor::new(track, path, Keymap::preset("qwerty").unwrap())
+    }
+
+    fn rows(ed: &Editor) -> String {
+        write_rows(&ed.session.editor.seq().fields)
+    }
+
+    // Press each key in turn, expecting none of them to quit.
+    fn press(ed: &mut Editor, keys: &[Key]) {
+        for &key in keys {
+            assert!(ed.key(key), "{:?} quit", key);
+        }
+    }
+
+    #[test]
+    fn notes_and_commands() {
+        let (mut ed, _) = editor(PathBuf::from("song.hzt"));
+        press(&mut ed, &[Key::Char('z'), Key::Char('Q'), Key::Char('1')]);
+        assert_eq!(rows(&ed), "C-4000    000\nC-5000    000\n---000    000\n");
+        assert!(ed.modified);
+        press(&mut ed, &[Key::Home, Key::Right, Key::Char('c'), Key::Right,
+            Key::Char('2'), Key::Char('f')]);
+        assert_eq!(rows(&ed), "C-4C2F    000\nC-5000    000\n---000    000\n");
+        // keys that mean nothing in the column are ignored.
+        press(&mut ed, &[Key::Tab, Key::Char('a'), Key::Char('f'), Key::Ctrl('e'), Key::Char('x')]);
+        assert_eq!(ed.session.editor.chan(), 1);
+        assert_eq!(rows(&ed), "C-4C2F    000\nC-5000 D-4000\n---000    000\n");
+    }
+
+    #[test]
+    fn rows_and_undo() {
+        let (mut ed, _) = editor(PathBuf::from("song.hzt"));
+        press(&mut ed, &[Key::Char('z'), Key::Home, Key::Insert]);
+        assert_eq!(rows(&ed), "C-4000    000\n   000    000\n   000    000\n   000    000\n");
+        assert_eq!(ed.session.editor.row(), 1);
+        press(&mut ed, &[Key::Delete, Key::Delete]);
+        assert_eq!(rows(&ed), "   000    000\n   000    000\n");
+        press(&mut ed, &[Key::Ctrl('z'), Key::Ctrl('z')]);
+        assert_eq!(rows(&ed), "C-4000    000\n   000    000\n   000    000\n   000    000\n");
+        // transposing needs a block.
+        press(&mut ed, &[Key::Ctrl('y'), Key::Alt('Q')]);
+        assert_eq!(rows(&ed), "C-4000    000\n   000    000\n   000    000\n");
+    }
+
+    #[test]
+    fn modifiers_pick_the_binding() {
+        let (mut ed, _) = editor(PathBuf::from("song.hzt"));
+        press(&mut ed, &[Key::Char('z'), Key::Up, Key::Ctrl('b'), Key::Down]);
+        press(&mut ed, &[Key::Alt('q')]);
+        assert_eq!(rows(&ed), "C#4000    000\n   000    000\n   000    000\n");
+        press(&mut ed, &[Key::Alt('Q')]);
+        assert_eq!(rows(&ed), "C#5000    000\n   000    000\n   000    000\n");
+        // alt and ctrl with an unbound key do nothing, not enter a note.
+        press(&mut ed, &[Key::Alt('z'), Key::Ctrl('w')]);
+        assert_eq!(rows(&ed), "C#5000    000\n   000    000\n   000    000\n");
+    }
+
+    #[test]
+    fn transport() {
+        let (mut ed, mut player) = editor(PathBuf::from("song.hzt"));
+        press(&mut ed, &[Key::Down, Key::Ctrl('r')]);
+        player.next();
+        assert_eq!(ed.session.remote.status().transport, Transport::Song);
+        assert_eq!(ed.session.remote.status().row, 1);
+        press(&mut ed, &[Key::Char(' ')]);
+        player.next();
+        assert!(ed.session.remote.status().paused);
+        press(&mut ed, &[Key::Esc]);
+        player.next();
+        assert_eq!(ed.session.remote.status().transport, Transport::Stopped);
+        assert!(!ed.modified);
+    }
+
+    #[test]
+    fn save_and_quit() {
+        let path = env::temp_dir().join(format!("hztrack-term-{}.hzt", ::std::process::id()));
+        let (mut ed, _) = editor(path.clone());
+        assert!(!ed.key(Key::Ctrl('q')));
+
+        press(&mut ed, &[Key::Char('z'), Key::Ctrl('q')]);
+        assert!(ed.message.starts_with("unsaved changes"));
+        // anything in between cancels the quit.
+        press(&mut ed, &[Key::Down, Key::Ctrl('q')]);
+        assert!(!ed.key(Key::Ctrl('q')));
+
+        press(&mut ed, &[Key::Ctrl('s')]);
+        assert!(!ed.modified && ed.message.starts_with("saved"));
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(saved.ends_with("pattern\nC-4000    000\n   000    000\n   000    000\n"));
+        assert!(!ed.key(Key::Ctrl('q')));
+    }
+}
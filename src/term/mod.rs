@@ -0,0 +1,254 @@
+// Terminal frontends, drawn with ANSI escapes, with no SDL video needed.
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use hztrack::keymap::{self, Chord};
+
+mod editor;
+mod player;
+pub use self::editor::Editor;
+pub use self::player::PlayerView;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Insert,
+    Delete,
+    Backspace,
+    Enter,
+    Tab,
+    Esc,
+    Ctrl(char),
+    Alt(char),
+}
+
+impl Key {
+    pub fn chord(&self) -> Chord {
+        let key = match *self {
+            Key::Char(c) | Key::Ctrl(c) | Key::Alt(c) => keymap::Key::Char(c),
+            Key::Up => keymap::Key::Up,
+            Key::Down => keymap::Key::Down,
+            Key::Left => keymap::Key::Left,
+            Key::Right => keymap::Key::Right,
+            Key::PageUp => keymap::Key::PageUp,
+            Key::PageDown => keymap::Key::PageDown,
+            Key::Home => keymap::Key::Home,
+            Key::End => keymap::Key::End,
+            Key::Insert => keymap::Key::Insert,
+            Key::Delete => keymap::Key::Delete,
+            Key::Backspace => keymap::Key::Backspace,
+            Key::Enter => keymap::Key::Enter,
+            Key::Tab => keymap::Key::Tab,
+            Key::Esc => keymap::Key::Esc,
+        };
+        Chord {
+            key: key,
+            ctrl: matches!(*self, Key::Ctrl(_)),
+            alt: matches!(*self, Key::Alt(_)),
+        }
+    }
+}
+
+// Puts the terminal in raw mode for as long as it lives.
+pub struct Terminal {
+    saved:  String, // stty settings to restore
+    input:  Vec<u8>,
+}
+
+impl Terminal {
+    pub fn raw() -> Result<Terminal, String> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "-ixon", "min", "0", "time", "0"])?;
+        print!("\x1b[?25l\x1b[2J");
+        Ok(Terminal {
+            saved: saved.trim().to_string(),
+            input: vec![],
+        })
+    }
+    // Keys pressed since the last call; never blocks.
+    pub fn keys(&mut self) -> Vec<Key> {
+        let mut buf = [0; 64];
+        let mut more = false;
+        if let Ok(n) = io::stdin().read(&mut buf) {
+            self.input.extend_from_slice(&buf[..n]);
+            more = n > 0;
+        }
+        let mut keys = vec![];
+        while !self.input.is_empty() {
+            // a key split across reads waits for the rest, until a read
+            // brings nothing more.
+            let (key, len) = match parse_key(&self.input, more) {
+                Some(parsed) => parsed,
+                None => break,
+            };
+            self.input.drain(..len);
+            if let Some(key) = key {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+    // Draw a whole screen of lines, from the top left.
+    pub fn draw(&mut self, lines: &[String]) {
+        let mut out = String::from("\x1b[H");
+        for line in lines {
+            out.push_str(line);
+            out.push_str("\x1b[K\r\n");
+        }
+        out.push_str("\x1b[J");
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\r\n");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+pub fn inverse(text: &str) -> String {
+    format!("\x1b[7m{}\x1b[0m", text)
+}
+
+pub fn highlight(text: &str) -> String {
+    format!("\x1b[44m{}\x1b[0m", text)
+}
+
+fn stty(args: &[&str]) -> Result<String, String> {
+    let out = Command::new("stty").args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("stty: {}", e))?;
+    if !out.status.success() {
+        return Err("stdin is not a terminal".to_string());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+// Decode one key from the start of `input`, returning the bytes it used, or
+// None if `input` stops partway through a key and `more` may follow.
+fn parse_key(input: &[u8], more: bool) -> Option<(Option<Key>, usize)> {
+    let parsed = match input {
+        [0x1b] if more => return None,
+        [0x1b, b'[', rest @ ..] | [0x1b, b'O', rest @ ..] => {
+            let end = match rest.iter().position(|&b| b.is_ascii_alphabetic() || b == b'~') {
+                Some(end) => end,
+                None if more => return None,
+                None => return Some((Some(Key::Esc), 1)),
+            };
+            let key = match &rest[..end + 1] {
+                b"A" => Some(Key::Up),
+                b"B" => Some(Key::Down),
+                b"C" => Some(Key::Right),
+                b"D" => Some(Key::Left),
+                b"H" | b"1~" => Some(Key::Home),
+                b"F" | b"4~" => Some(Key::End),
+                b"2~" => Some(Key::Insert),
+                b"3~" => Some(Key::Delete),
+                b"5~" => Some(Key::PageUp),
+                b"6~" => Some(Key::PageDown),
+                _ => None,
+            };
+            (key, end + 3)
+        }
+        [0x1b, c @ 0x20..=0x7e, ..] => (Some(Key::Alt(*c as char)), 2),
+        [0x1b, ..] => (Some(Key::Esc), 1),
+        [b'\r', ..] | [b'\n', ..] => (Some(Key::Enter), 1),
+        [b'\t', ..] => (Some(Key::Tab), 1),
+        [0x7f, ..] | [0x08, ..] => (Some(Key::Backspace), 1),
+        [c @ 0x01..=0x1a, ..] => (Some(Key::Ctrl((c + b'a' - 1) as char)), 1),
+        _ => {
+            let len = match input[0] {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            if len > input.len() && more {
+                return None;
+            }
+            let len = len.min(input.len());
+            // a bad byte is dropped alone, so the keys after it survive.
+            match ::std::str::from_utf8(&input[..len]).ok().and_then(|s| s.chars().next()) {
+                Some(c) => (Some(Key::Char(c)), len),
+                None => (None, 1),
+            }
+        }
+    };
+    Some(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_key, Key};
+    use hztrack::keymap::{self, Chord};
+
+    // Every key in `input`, with none to follow.
+    fn keys(input: &[u8]) -> Vec<Option<Key>> {
+        let mut keys = vec![];
+        let mut rest = input;
+        while !rest.is_empty() {
+            let (key, len) = parse_key(rest, false).unwrap();
+            keys.push(key);
+            rest = &rest[len..];
+        }
+        keys
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(keys(b"\x1b[A\x1b[B\x1bOC\x1bOD"),
+            [Some(Key::Up), Some(Key::Down), Some(Key::Right), Some(Key::Left)]);
+        assert_eq!(keys(b"\x1b[H\x1b[1~\x1b[F\x1b[4~"),
+            [Some(Key::Home), Some(Key::Home), Some(Key::End), Some(Key::End)]);
+        assert_eq!(keys(b"\x1b[2~\x1b[3~\x1b[5~\x1b[6~"),
+            [Some(Key::Insert), Some(Key::Delete), Some(Key::PageUp), Some(Key::PageDown)]);
+        // unknown sequences are swallowed whole, modifiers and all.
+        assert_eq!(keys(b"\x1b[1;5A\x1b[15~x"), [None, None, Some(Key::Char('x'))]);
+    }
+
+    #[test]
+    fn modifiers_and_controls() {
+        assert_eq!(keys(b"\x1bx\x1bQ\x1b\x1b"),
+            [Some(Key::Alt('x')), Some(Key::Alt('Q')), Some(Key::Esc), Some(Key::Esc)]);
+        assert_eq!(keys(b"\x01\x11\x1a"),
+            [Some(Key::Ctrl('a')), Some(Key::Ctrl('q')), Some(Key::Ctrl('z'))]);
+        assert_eq!(keys(b"\r\n\t\x7f\x08"), [Some(Key::Enter), Some(Key::Enter),
+            Some(Key::Tab), Some(Key::Backspace), Some(Key::Backspace)]);
+
+        let chord = Key::Ctrl('s').chord();
+        assert!(chord.key == keymap::Key::Char('s') && chord.ctrl && !chord.alt);
+        let chord = Key::Alt('=').chord();
+        assert!(chord.key == keymap::Key::Char('=') && !chord.ctrl && chord.alt);
+        assert!(Key::PageUp.chord() == Chord::key(keymap::Key::PageUp));
+    }
+
+    #[test]
+    fn split_and_malformed_input() {
+        // a key cut short waits for the rest, unless none is coming.
+        for partial in &[&b"\x1b"[..], b"\x1b[", b"\x1b[3", b"\xc3", b"\xe2\x82"] {
+            assert_eq!(parse_key(partial, true), None, "{:?}", partial);
+        }
+        assert_eq!(parse_key(b"\x1b", false), Some((Some(Key::Esc), 1)));
+        assert_eq!(parse_key(b"\x1b[3", false), Some((Some(Key::Esc), 1)));
+        assert_eq!(keys("é€".as_bytes()), [Some(Key::Char('é')), Some(Key::Char('€'))]);
+        assert_eq!(keys(b"\xc3"), [None]);
+        assert_eq!(keys(b"\xff\xfe"), [None, None]);
+        assert_eq!(keys(b"\xc3a\xbfb"), [None, Some(Key::Char('a')), None, Some(Key::Char('b'))]);
+        assert_eq!(keys(b"\x00"), [Some(Key::Char('\0'))]);
+    }
+}
@@ -0,0 +1,134 @@
+// Plays a song in the terminal, with the pattern scrolling past and a
+// volume meter per channel.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hztrack::player::{self, Remote};
+use hztrack::sequence::Sequence;
+use hztrack::track::{Track, Edit, Transport};
+use term::{Terminal, Key, inverse};
+
+const CONTEXT: usize = 8;   // rows shown above and below the playing row
+const FIELD_W: usize = 6;
+const FRAME: u64 = 30;      // milliseconds between redraws
+
+const HELP: &'static str =
+    "enter play  p loop  space pause  s stop  1-9 mute  !-( solo  q quit";
+
+pub struct PlayerView {
+    seq:        Sequence,
+    remote:     Remote,
+    duration:   f64,
+    elapsed:    Duration,   // time spent playing, not counting pauses
+}
+
+impl PlayerView {
+    pub fn new(mut track: Track) -> (PlayerView, player::Player) {
+        let duration = track.analyze().seconds;
+        let seq = track.seq.clone();
+        track.play_song();
+        let (player, remote) = player::new(track);
+        let view = PlayerView {
+            seq: seq,
+            remote: remote,
+            duration: duration,
+            elapsed: Duration::from_secs(0),
+        };
+        (view, player)
+    }
+    // Run until the user quits.
+    pub fn run(&mut self, term: &mut Terminal) {
+        let mut last = Instant::now();
+        loop {
+            for key in term.keys() {
+                if !self.key(key) {
+                    self.remote.send(Edit::Stop);
+                    return;
+                }
+            }
+            let now = Instant::now();
+            let status = self.remote.status().clone();
+            if status.transport != Transport::Stopped && !status.paused {
+                self.elapsed += now - last;
+            }
+            last = now;
+            let lines = self.draw();
+            term.draw(&lines);
+            thread::sleep(Duration::from_millis(FRAME));
+        }
+    }
+    // Returns false to quit.
+    fn key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char('q') | Key::Esc | Key::Ctrl('c') => return false,
+            Key::Enter => {
+                self.elapsed = Duration::from_secs(0);
+                self.remote.send(Edit::PlaySong);
+            }
+            Key::Char('p') => {
+                self.elapsed = Duration::from_secs(0);
+                self.remote.send(Edit::PlayPattern);
+            }
+            Key::Char('s') => self.remote.send(Edit::Stop),
+            Key::Char(' ') => {
+                let edit = if self.remote.status().paused {
+                    Edit::Resume
+                } else {
+                    Edit::Pause
+                };
+                self.remote.send(edit);
+            }
+            Key::Char(c @ '1'..='9') => {
+                self.remote.send(Edit::ToggleMute(c as usize - '1' as usize));
+            }
+            Key::Char(c) => if let Some(i) = "!@#$%^&*(".find(c) {
+                self.remote.send(Edit::ToggleSolo(i));
+            },
+            _ => {},
+        }
+        true
+    }
+    fn draw(&mut self) -> Vec<String> {
+        let status = self.remote.status().clone();
+        let state = match (status.transport, status.paused) {
+            (Transport::Stopped, _) => "stopped",
+            (_, true) => "paused",
+            (Transport::Song, _) => "playing",
+            (Transport::Pattern, _) => "looping",
+        };
+        let mut lines = vec![
+            format!("hztrack  {:<8} row {:3}/{:<3}  {} / {}",
+                state, status.row, self.seq.len(),
+                time(self.elapsed.as_secs() as f64), time(self.duration)),
+            String::new(),
+        ];
+        let meters: String = (0..self.seq.width()).map(|i| {
+            let level = status.meters.get(i).map_or(0, |&m| m as usize);
+            let bars = (level * FIELD_W).div_ceil(0x40);
+            format!("{:<w$} ", "#".repeat(bars.min(FIELD_W)), w = FIELD_W)
+        }).collect();
+        lines.push(format!("     {}", meters));
+        lines.push(String::new());
+        for i in 0..CONTEXT * 2 + 1 {
+            let row = (status.row + i).wrapping_sub(CONTEXT);
+            if row >= self.seq.len() {
+                lines.push(String::new());
+                continue;
+            }
+            let fields: Vec<String> = self.seq.fields[row].iter()
+                .map(|f| format!("{}", f))
+                .collect();
+            let line = format!("{:3}  {}", row, fields.join(" "));
+            lines.push(if row == status.row { inverse(&line) } else { line });
+        }
+        lines.push(String::new());
+        lines.push(HELP.to_string());
+        lines
+    }
+}
+
+fn time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
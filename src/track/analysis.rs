@@ -0,0 +1,80 @@
+use track::{Track, Transport};
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub seconds:    f64,
+    pub rows:       usize,          // rows played, counting repeats
+    pub loop_row:   Option<usize>,  // row the song jumps back to, if it loops
+}
+
+impl Track {
+    // Simulate playing the song from the top, without mixing, until it
+    // either ends or returns to a row it has already played.
+    pub fn analyze(&self) -> Analysis {
+        let mut track = self.clone();
+        track.play_song();
+        let mut visited = vec![false; track.seq.len()];
+        let mut out = Analysis {
+            seconds: 0.0,
+            rows: 0,
+            loop_row: None,
+        };
+        loop {
+            track.step();
+            if track.transport == Transport::Stopped {
+                break;
+            }
+            // a beat was just processed: we've entered a new row.
+            if track.tick_count == 1 {
+                if visited[track.row] {
+                    out.loop_row = Some(track.row);
+                    break;
+                }
+                visited[track.row] = true;
+                out.rows += 1;
+            }
+            out.seconds += 60.0 / track.ticks_per_minute();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use file::parse_rows;
+    use track::{Track, Transport};
+
+    fn analyze(text: &str) -> (usize, f64, Option<usize>) {
+        let a = Track::new(parse_rows(text).unwrap()).analyze();
+        (a.rows, a.seconds, a.loop_row)
+    }
+
+    #[test]
+    fn songs_that_end() {
+        // 120 rows per minute until told otherwise, whatever the speed.
+        let (rows, seconds, loop_row) = analyze("   000\n   000\n   000\n   000\n");
+        assert_eq!((rows, loop_row), (4, None));
+        assert!((seconds - 2.0).abs() < 1e-9);
+        let (_, seconds, _) = analyze("   F02\n   000\n");
+        assert!((seconds - 1.0).abs() < 1e-9);
+        let (_, seconds, _) = analyze("   000\n   F96\n   000\n");
+        assert!((seconds - 0.5 - 2.0 * 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn songs_that_loop() {
+        let (rows, seconds, loop_row) = analyze("   000\n   000\n   B01\n   000\n");
+        assert_eq!((rows, loop_row), (3, Some(1)));
+        assert!((seconds - 1.5).abs() < 1e-9);
+        let (rows, _, loop_row) = analyze("   B00\n");
+        assert_eq!((rows, loop_row), (1, Some(0)));
+    }
+
+    #[test]
+    fn leaves_the_track_alone() {
+        let mut track = Track::new(parse_rows("   000\n   000\n").unwrap());
+        track.stop();
+        track.analyze();
+        assert_eq!((track.transport(), track.row()), (Transport::Stopped, 0));
+    }
+}
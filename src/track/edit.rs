@@ -0,0 +1,41 @@
+use sequence::Field;
+use track::Track;
+
+// A change to a track, sent from the UI to whoever owns the track.
+#[derive(Clone)]
+pub enum Edit {
+    SetField(usize, usize, Field),  // row, column
+    InsertRow(usize),
+    RemoveRow(usize),
+    Stop,
+    PlaySong,
+    PlayPattern,
+    PlayFrom(usize),
+    Pause,
+    Resume,
+    ToggleMute(usize),
+    ToggleSolo(usize),
+    MixVol(usize, u8),
+}
+
+impl Track {
+    pub fn apply(&mut self, edit: Edit) {
+        match edit {
+            Edit::SetField(row, col, field) => self.seq.set_field(row, col, field),
+            Edit::InsertRow(row) => self.seq.insert_row(row),
+            Edit::RemoveRow(row) => {
+                self.seq.remove_row(row);
+                self.row = self.row.min(self.seq.len() - 1);
+            }
+            Edit::Stop => self.stop(),
+            Edit::PlaySong => self.play_song(),
+            Edit::PlayPattern => self.play_pattern(),
+            Edit::PlayFrom(row) => self.play_from(row),
+            Edit::Pause => self.pause(),
+            Edit::Resume => self.resume(),
+            Edit::ToggleMute(i) => self.toggle_mute(i),
+            Edit::ToggleSolo(i) => self.toggle_solo(i),
+            Edit::MixVol(i, vol) => self.set_mix_vol(i, vol),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// Instruments: where a channel's samples come from, and how its volume,
+// panning and pitch evolve over the life of a note.
+
+use std::sync::Arc;
+
+use mixer::{Filter, FilterMode, Source, Osc, FmPatch};
+
+#[derive(Clone)]
+pub struct Instrument {
+    pub source:     Source,
+    pub vol_env:    Option<Envelope>,   // 0..64
+    pub pan_env:    Option<Envelope>,   // -64 (left) ..64 (right)
+    pub pitch_env:  Option<Envelope>,   // semitones
+    pub filter_env: Option<Envelope>,   // 0..64, scales the cutoff
+    pub fadeout:    u16,    // volume lost per tick after release, of 0x10000
+}
+
+#[derive(Clone)]
+pub struct Envelope {
+    pub points:     Vec<(u16, i8)>,     // (tick, value), sorted by tick
+    pub sustain:    Option<usize>,      // point held until the note is released
+    pub loop_range: Option<(usize, usize)>, // points to loop between
+}
+
+// Playback position within an instrument's envelopes.
+#[derive(Clone)]
+pub struct EnvState {
+    pos:            [u16; 4],
+    pub released:   bool,
+    pub fade:       u32,    // 0x10000 = full volume
+}
+
+const VOL: usize = 0;
+const PAN: usize = 1;
+const PITCH: usize = 2;
+const FILTER: usize = 3;
+
+impl Instrument {
+    pub fn pcm(off: usize, len: u32, rate: f32, lp: Option<u32>) -> Self {
+        Instrument::new(Source::Pcm{off: off, len: len, rate: rate, lp: lp})
+    }
+    pub fn osc(osc: Osc) -> Self {
+        Instrument::new(Source::Osc(osc))
+    }
+    pub fn fm(patch: FmPatch) -> Self {
+        Instrument::new(Source::Fm(Arc::new(patch)))
+    }
+    pub fn new(source: Source) -> Self {
+        Instrument {
+            source: source,
+            vol_env: None,
+            pan_env: None,
+            pitch_env: None,
+            filter_env: None,
+            fadeout: 0,
+        }
+    }
+    fn env(&self, which: usize) -> Option<&Envelope> {
+        match which {
+            VOL => self.vol_env.as_ref(),
+            PAN => self.pan_env.as_ref(),
+            PITCH => self.pitch_env.as_ref(),
+            FILTER => self.filter_env.as_ref(),
+            _ => unreachable!(),
+        }
+    }
+    // Whether a released note should die out gradually, rather than cut.
+    pub fn fades(&self) -> bool {
+        match self.source {
+            Source::Fm(_) => true,
+            _ => self.vol_env.is_some() || self.fadeout != 0,
+        }
+    }
+    // Volume scale, 0..64.
+    pub fn vol(&self, st: &EnvState) -> i32 {
+        let env = self.vol_env.as_ref().map_or(64, |e| e.value(st.pos[VOL]));
+        (env * (st.fade >> 4) as i32) >> 12
+    }
+    pub fn pan(&self, st: &EnvState) -> i32 {
+        self.pan_env.as_ref().map_or(0, |e| e.value(st.pos[PAN]))
+    }
+    pub fn pitch(&self, st: &EnvState) -> i32 {
+        self.pitch_env.as_ref().map_or(0, |e| e.value(st.pos[PITCH]))
+    }
+    // Apply the filter envelope, which switches on the low-pass if the
+    // channel has no filter of its own.
+    pub fn filter(&self, st: &EnvState, mut filter: Filter) -> Filter {
+        if let Some(ref env) = self.filter_env {
+            let scale = env.value(st.pos[FILTER]).clamp(0, 64);
+            filter.cutoff = ((filter.cutoff as i32 * scale) >> 6) as u8;
+            if filter.mode == FilterMode::Off {
+                filter.mode = FilterMode::LowPass;
+            }
+        }
+        filter
+    }
+}
+
+impl Envelope {
+    pub fn value(&self, pos: u16) -> i32 {
+        let next = match self.points.iter().position(|p| p.0 > pos) {
+            Some(0) => return self.points[0].1 as i32,
+            Some(i) => i,
+            None => return self.points.last().map_or(0, |p| p.1 as i32),
+        };
+        let (t0, v0) = self.points[next - 1];
+        let (t1, v1) = self.points[next];
+        let (t0, v0, t1, v1) = (t0 as i32, v0 as i32, t1 as i32, v1 as i32);
+        v0 + (v1 - v0) * (pos as i32 - t0) / (t1 - t0)
+    }
+    fn advance(&self, pos: u16, released: bool) -> u16 {
+        let tick = |i: usize| self.points.get(i).map_or(0, |p| p.0);
+        if let (false, Some(sus)) = (released, self.sustain) {
+            if pos >= tick(sus) {
+                return tick(sus);
+            }
+        }
+        let pos = pos.saturating_add(1);
+        match self.loop_range {
+            Some((start, end)) if pos > tick(end) => tick(start),
+            _ => pos,
+        }
+    }
+}
+
+impl EnvState {
+    pub fn new() -> Self {
+        EnvState {
+            pos: [0; 4],
+            released: false,
+            fade: 0x10000,
+        }
+    }
+    pub fn tick(&mut self, inst: &Instrument) {
+        for which in 0..4 {
+            if let Some(env) = inst.env(which) {
+                self.pos[which] = env.advance(self.pos[which], self.released);
+            }
+        }
+        if self.released {
+            self.fade = self.fade.saturating_sub(inst.fadeout as u32);
+        }
+    }
+}
+
+impl Default for EnvState {
+    fn default() -> Self {
+        EnvState::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Instrument, Envelope, EnvState};
+
+    fn inst(points: Vec<(u16, i8)>, sustain: Option<usize>, loop_range: Option<(usize, usize)>)
+        -> Instrument
+    {
+        let mut inst = Instrument::pcm(0, 1, 1.0, None);
+        inst.vol_env = Some(Envelope { points: points, sustain: sustain, loop_range: loop_range });
+        inst
+    }
+
+    // Volume over the next `ticks`, starting from the current one.
+    fn vols(inst: &Instrument, st: &mut EnvState, ticks: usize) -> Vec<i32> {
+        (0..ticks).map(|_| {
+            let vol = inst.vol(st);
+            st.tick(inst);
+            vol
+        }).collect()
+    }
+
+    #[test]
+    fn sustain_then_fade() {
+        let mut inst = inst(vec![(0, 0), (4, 64), (8, 32), (12, 0)], Some(1), None);
+        inst.fadeout = 0x4000;
+        assert!(inst.fades());
+        let mut st = EnvState::new();
+        assert_eq!(vols(&inst, &mut st, 8), [0, 16, 32, 48, 64, 64, 64, 64]);
+        st.released = true;
+        // the envelope moves on from the sustain point as the fade starts.
+        assert_eq!(vols(&inst, &mut st, 6), [64, 42, 24, 10, 0, 0]);
+    }
+
+    #[test]
+    fn loops() {
+        let inst = inst(vec![(0, 0), (2, 20), (4, 40)], None, Some((1, 2)));
+        let mut st = EnvState::new();
+        assert_eq!(vols(&inst, &mut st, 9), [0, 10, 20, 30, 40, 20, 30, 40, 20]);
+        // a release leaves a loop running, but fades out if asked.
+        st.released = true;
+        assert_eq!(vols(&inst, &mut st, 3), [30, 40, 20]);
+    }
+
+    #[test]
+    fn sustain_inside_a_loop() {
+        let inst = inst(vec![(0, 64), (2, 0), (4, 64)], Some(1), Some((0, 2)));
+        let mut st = EnvState::new();
+        assert_eq!(vols(&inst, &mut st, 5), [64, 32, 0, 0, 0]);
+        st.released = true;
+        assert_eq!(vols(&inst, &mut st, 5), [0, 32, 64, 64, 32]);
+    }
+
+    #[test]
+    fn no_envelope() {
+        let mut inst = Instrument::pcm(0, 1, 1.0, None);
+        assert!(!inst.fades());
+        let mut st = EnvState::new();
+        st.released = true;
+        assert_eq!(vols(&inst, &mut st, 2), [64, 64]);
+        inst.fadeout = 0x8000;
+        assert!(inst.fades());
+        assert_eq!(vols(&inst, &mut st, 3), [64, 32, 0]);
+    }
+}
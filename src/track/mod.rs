@@ -1,17 +1,59 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
-use mixer::{Controller, MixerIn, ChannelIn};
+use mixer::{Controller, MixerIn, ChannelIn, Filter, FilterMode};
+use mixer::{SENDS, DELAY, REVERB, CHORUS};
 use sequence::{Sequence, Field, Command, Note};
 
+mod analysis;
+mod edit;
+mod instrument;
+mod transport;
+pub use self::analysis::Analysis;
+pub use self::edit::Edit;
+pub use self::instrument::{Instrument, Envelope, EnvState};
+pub use self::transport::Transport;
+
+// Every command id that playback understands.
+pub const COMMANDS: &'static str = "0123BCDEFGHJNPQRTUVWY";
+
+#[derive(Clone)]
 pub struct Track {
     pub seq:    Sequence,
     chan:       Vec<Channel>,
     row_jump:   Option<usize>,
     row:        usize,
     tick_count: u8,
-    tick_rate:  u8,
+    pub grooves: Vec<Vec<u8>>,  // ticks per row, repeating; 0 plays first
+    groove:     Vec<u8>,
+    groove_pos: usize,
     bpm:        u8,
+    bpm_fine:   u8,     // fractional bpm, in 1/256ths
+    beat_bpm:   bool,   // bpm counts beats of 24 ticks rather than rows
+    global_vol: u8,     // 0x80 = unity
     pcm:        Arc<Vec<i8>>,
+    pub instruments: Vec<Instrument>,
+    mix:        Vec<Mix>,
+    transport:  Transport,
+    paused:     bool,
+}
+
+// Mixing state set by the user, kept apart from pattern data.
+#[derive(Clone)]
+pub struct Mix {
+    pub mute: bool,
+    pub solo: bool,
+    pub vol:  u8,   // 0x40 = unity
+}
+
+impl Mix {
+    fn new() -> Self {
+        Mix {
+            mute: false,
+            solo: false,
+            vol: 0x40,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -21,6 +63,13 @@ pub struct Channel {
     porta_note: u8,
     cmd: Command,
     vol: i16,
+    trig: bool,
+    inst: usize,
+    env: EnvState,
+    filter: Filter,
+    duty: u8,
+    wave_pos: u8,
+    sends: [u8; SENDS],
 }
 
 impl Channel {
@@ -31,6 +80,13 @@ impl Channel {
             porta_note: 0,
             cmd: Command::zero(),
             vol: 0,
+            trig: false,
+            inst: 0,
+            env: EnvState::new(),
+            filter: Filter::off(),
+            duty: 0x80,
+            wave_pos: 0,
+            sends: [0; SENDS],
         }
     }
 }
@@ -42,24 +98,140 @@ impl Track {
             row: 0,
             row_jump: None,
             tick_count: 0,
-            tick_rate: 6,
+            grooves: vec![vec![6]],
+            groove: vec![6],
+            groove_pos: 0,
             bpm: 120,
+            bpm_fine: 0,
+            beat_bpm: false,
+            global_vol: 0x80,
             pcm: Arc::new((0..256)
-                .map(|i| ((i as f64 / 128.0 * 3.1415).sin() * 127.0) as i8)
+                .map(|i| ((i as f64 / 128.0 * PI).sin() * 127.0) as i8)
                 .collect()),
+            instruments: vec![Instrument::pcm(0, 256, 256.0, Some(0))],
+            mix: vec![],
+            transport: Transport::Pattern,
+            paused: false,
+        }
+    }
+    pub fn mix(&mut self, i: usize) -> &mut Mix {
+        if self.mix.len() <= i {
+            self.mix.resize(i + 1, Mix::new());
+        }
+        &mut self.mix[i]
+    }
+    pub fn toggle_mute(&mut self, i: usize) {
+        let mix = self.mix(i);
+        mix.mute = !mix.mute;
+    }
+    pub fn toggle_solo(&mut self, i: usize) {
+        let mix = self.mix(i);
+        mix.solo = !mix.solo;
+    }
+    pub fn set_mix_vol(&mut self, i: usize, vol: u8) {
+        self.mix(i).vol = vol;
+    }
+    fn instrument(&self, i: usize) -> &Instrument {
+        self.instruments.get(i).unwrap_or(&self.instruments[0])
+    }
+    fn mix_vol(&self, i: usize) -> u8 {
+        let any_solo = self.mix.iter().any(|m| m.solo);
+        match self.mix.get(i) {
+            Some(m) if m.mute || (any_solo && !m.solo) => 0,
+            Some(m) => m.vol,
+            None if any_solo => 0,
+            None => 0x40,
+        }
+    }
+    // Append sample data, returning its offset for use in an Instrument.
+    pub fn add_sample(&mut self, data: &[i8]) -> usize {
+        let pcm = Arc::make_mut(&mut self.pcm);
+        pcm.extend_from_slice(data);
+        pcm.len() - data.len()
+    }
+    pub fn pcm(&self) -> &[i8] {
+        &self.pcm
+    }
+    pub fn ticks_per_minute(&self) -> f64 {
+        // tempo follows the groove's average, so swing doesn't drag.
+        let ticks = if self.beat_bpm {
+            24.0
+        } else {
+            self.groove.iter().map(|&t| t as f64).sum::<f64>() / self.groove.len() as f64
+        };
+        (self.bpm as f64 + self.bpm_fine as f64 / 256.0) * ticks
+    }
+    // Ticks in the current row.
+    fn tick_rate(&self) -> u8 {
+        self.groove[self.groove_pos % self.groove.len()]
+    }
+    fn set_speed(&mut self, speed: u8) {
+        if self.groove != [speed] {
+            self.groove = vec![speed];
+            self.groove_pos = 0;
+        }
+    }
+    pub fn set_groove(&mut self, i: usize) {
+        match self.grooves.get(i) {
+            Some(groove) if !groove.is_empty() => {
+                self.groove = groove.iter().map(|&t| t.max(1)).collect();
+                self.groove_pos = 0;
+            }
+            _ => {},
+        }
+    }
+    // Advance playback by one tick.
+    fn step(&mut self) {
+        let width = self.seq.width();
+        if self.tick_count >= self.tick_rate() {
+            self.tick_count = 0;
+            self.groove_pos = (self.groove_pos + 1) % self.groove.len();
+            let next = self.row + 1;
+            // a jump past the end, left by removing rows, is ignored.
+            self.row = match self.row_jump.take().filter(|&row| row < self.seq.len()) {
+                Some(row) => row,
+                None if next < self.seq.len() => next,
+                None if self.transport == Transport::Pattern => 0,
+                None => return self.stop(),
+            };
+        }
+        if self.tick_count == 0 {
+            for i in 0..width {
+                self.channel_beat(i);
+            }
+            self.row_tempo();
         }
+        for i in 0..width {
+            self.channel_tick(i)
+        }
+        // borrowed apart, so the instrument needn't be copied.
+        let instruments = &self.instruments;
+        for chan in self.chan.iter_mut().take(width) {
+            let inst = instruments.get(chan.inst).unwrap_or(&instruments[0]);
+            chan.env.tick(inst);
+        }
+        self.tick_count += 1;
     }
     fn channel_beat(&mut self, i: usize) {
         let field = &self.seq.get_field(self.row, i);
+        let fades = self.instrument(self.chan[i].inst).fades();
         let chan = &mut self.chan[i];
+        if field.cmd.id == b'N' {
+            chan.inst = field.cmd.data as usize;
+        }
         match field.note {
             Note::On(n) => {
                 match field.cmd.id {
                     b'3' => chan.porta_note = n,
-                    _ => chan.note = (n as u16)<<8,
+                    _ => {
+                        chan.note = (n as u16)<<8;
+                        chan.trig = true;
+                        chan.env = EnvState::new();
+                    }
                 }
                 chan.vol = 0x40;
             }
+            Note::Off if fades => chan.env.released = true,
             Note::Off => chan.vol = 0,
             Note::Hold => {},
         }
@@ -71,9 +243,46 @@ impl Track {
         }
         chan.cmd.id = field.cmd.id;
     }
+    // Tempo commands take effect once, at the start of the row, in a fixed
+    // order whatever their channels: grooves, then speed and tempo, then
+    // fine tempo, so that a U isn't undone by an F further along the row.
+    fn row_tempo(&mut self) {
+        for &id in b"GFEU" {
+            for i in 0..self.seq.width() {
+                let field = self.seq.get_field(self.row, i);
+                if field.cmd.id != id {
+                    continue;
+                }
+                let data = field.cmd.data;
+                match id {
+                    // G00 selects the first groove, so no effect memory.
+                    b'G' => self.set_groove(data as usize),
+                    b'F' => match self.chan[i].cmd.data {
+                        speed @ 0..=31 => self.set_speed(speed + 1),
+                        bpm => {
+                            self.bpm = bpm;
+                            self.bpm_fine = 0;
+                            self.beat_bpm = false;
+                        }
+                    },
+                    // ProTracker's tempo, in beats of 24 ticks whatever the
+                    // speed; the speed then only sets the length of a row.
+                    b'E' => {
+                        self.bpm = self.chan[i].cmd.data.max(32);
+                        self.bpm_fine = 0;
+                        self.beat_bpm = true;
+                    }
+                    // U00 clears the fine tempo, so no effect memory.
+                    _ => self.bpm_fine = data,
+                }
+            }
+        }
+    }
     fn channel_tick(&mut self, i: usize) {
         let chan = &mut self.chan[i];
         let field = &self.seq.get_field(self.row, i);
+        // slides move on every tick but the row's first, as in ProTracker.
+        let sliding = self.tick_count != 0;
         match field.cmd.id {
             b'0' => {
                 chan.add_note =
@@ -86,15 +295,15 @@ impl Track {
                         _ => unreachable!(),
                     };
             }
-            b'1' => chan.note = chan.note
+            b'1' if sliding => chan.note = chan.note
                 .saturating_add((chan.cmd.data as u16)<<4),
-            b'2' => chan.note = chan.note
+            b'2' if sliding => chan.note = chan.note
                 .saturating_sub((chan.cmd.data as u16)<<4),
-            b'3' => {
+            b'3' if sliding => {
                 let porta_note = (chan.porta_note as u16)<<8;
                 let rate = (chan.cmd.data as u16)<<4;
-                let diff = chan.note - porta_note;
-                if diff.abs() < rate {
+                let diff = chan.note as i32 - porta_note as i32;
+                if diff.abs() < rate as i32 {
                     chan.note = porta_note;
                 } else if diff > 0 {
                     chan.note -= rate;
@@ -102,15 +311,46 @@ impl Track {
                     chan.note += rate;
                 }
             }
-            b'F' => {
-                match chan.cmd.data {
-                    0...31 => self.tick_rate = chan.cmd.data + 1,
-                    32...255 => self.bpm = chan.cmd.data,
-                    _ => unreachable!(),
+            // tempo commands are applied by row_tempo.
+            b'E' | b'F' | b'G' | b'U' => {},
+            // volume has no effect memory, so that C00 silences.
+            b'C' => chan.vol = field.cmd.data.min(0x40) as i16,
+            b'B' => {
+                // a J in the same row gives the high byte of the row.
+                let (seq, row) = (&self.seq, self.row);
+                let page = (0..seq.width()).map(|c| &seq.get_field(row, c).cmd)
+                    .find(|cmd| cmd.id == b'J')
+                    .map_or(0, |cmd| cmd.data as usize);
+                self.row_jump = Some(page << 8 | chan.cmd.data as usize);
+            }
+            b'J' | b'N' => {},
+            b'P' => chan.duty = field.cmd.data,
+            b'T' => chan.wave_pos = field.cmd.data,
+            b'D' => chan.sends[DELAY] = field.cmd.data,
+            b'R' => chan.sends[REVERB] = field.cmd.data,
+            b'H' => chan.sends[CHORUS] = field.cmd.data,
+            // filter commands have no effect memory.
+            b'Y' => {
+                chan.filter.cutoff = field.cmd.data;
+                if chan.filter.mode == FilterMode::Off {
+                    chan.filter.mode = FilterMode::LowPass;
                 }
             }
-            b'B' => self.row_jump = Some(chan.cmd.data as usize),
-            c @ _ => panic!("unknown command id: {}", c as char),
+            b'Q' => {
+                chan.filter.mode = match field.cmd.hi() {
+                    1 => FilterMode::LowPass,
+                    2 => FilterMode::HighPass,
+                    3 => FilterMode::BandPass,
+                    _ => FilterMode::Off,
+                };
+                chan.filter.reso = field.cmd.lo() * 0x11;
+            }
+            // like C, so that V00 silences.
+            b'V' => self.global_vol = field.cmd.data.min(0x80),
+            b'W' if sliding => self.global_vol = (self.global_vol + chan.cmd.hi())
+                .min(0x80).saturating_sub(chan.cmd.lo()),
+            b'1' | b'2' | b'3' | b'W' => {},
+            c => panic!("unknown command id: {}", c as char),
         }
     }
 }
@@ -119,32 +359,146 @@ impl Controller for Track {
     fn next(&mut self) -> MixerIn {
         let width = self.seq.width();
         self.chan.resize(width, Channel::new());
-        if self.tick_count == self.tick_rate {
-            self.tick_count = 0;
-            self.row = self.row_jump.unwrap_or(
-                (self.row + 1) % self.seq.len());
-            self.row_jump = None;
-        }
-        if self.tick_count == 0 {
-            for i in 0..width {
-                self.channel_beat(i);
-            }
-        }
-        for i in 0..width {
-            self.channel_tick(i)
+        let playing = self.transport != Transport::Stopped && !self.paused;
+        if playing {
+            self.step();
         }
-        self.tick_count += 1;
-        MixerIn {
-            tick_rate: self.bpm as u16 * self.tick_rate as u16,
+        let out = MixerIn {
+            tick_rate: self.ticks_per_minute(),
+            global_vol: self.global_vol,
             pcm: self.pcm.clone(),
-            chan: self.chan.iter().map(|c|
+            chan: self.chan.iter().enumerate().map(|(i, c)| {
+                let inst = self.instrument(c.inst);
+                let pitch = inst.pitch(&c.env) << 8;
                 ChannelIn{
-                    note: c.note + c.add_note,
-                    pcm_off: 0,
-                    pcm_len: 256,
-                    pcm_rate: 256,
-                    vol: c.vol,
-                }).collect(),
+                    note: ((c.note + c.add_note) as i32 + pitch)
+                        .clamp(0, 0xffff) as u16,
+                    source: inst.source.clone(),
+                    duty: c.duty,
+                    wave_pos: c.wave_pos,
+                    vol: if playing {
+                        ((c.vol as i32 * inst.vol(&c.env)) >> 6) as i16
+                    } else {
+                        0
+                    },
+                    pan: inst.pan(&c.env).clamp(-64, 64) as i8,
+                    mix_vol: self.mix_vol(i),
+                    trig: playing && c.trig,
+                    gate: !c.env.released,
+                    filter: inst.filter(&c.env, c.filter),
+                    sends: c.sends,
+                }
+            }).collect(),
+        };
+        for c in self.chan.iter_mut() {
+            c.trig = false;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use file::parse_rows;
+    use mixer::Controller;
+    use track::{Track, Transport};
+
+    fn track(text: &str) -> Track {
+        let mut track = Track::new(parse_rows(text).unwrap());
+        track.play_song();
+        track
+    }
+
+    #[test]
+    fn fine_tempo_survives_tempo_in_any_channel() {
+        for text in &["   U80    F96\n", "   F96    U80\n"] {
+            let mut track = track(text);
+            track.next();
+            assert_eq!((track.bpm, track.bpm_fine), (0x96, 0x80), "{}", text);
+            assert_eq!(track.ticks_per_minute(), 150.5 * 6.0);
+        }
+    }
+
+    // Play whole rows, from the start of the next.
+    fn play_rows(track: &mut Track, rows: usize) {
+        for _ in 0..rows {
+            track.next();
+            while track.tick_count < track.tick_rate() {
+                track.next();
+            }
+        }
+    }
+
+    #[test]
+    fn slides_skip_the_first_tick() {
+        let mut track = track("C-5W01    101
+   000    000
+");
+        play_rows(&mut track, 1);
+        assert_eq!(track.global_vol, 0x80 - 5);
+        assert_eq!(track.chan[1].note, 5 * 0x10);
+    }
+
+    #[test]
+    fn global_volume_slides_saturate() {
+        let mut track = track("   V10\n   W0F\n   WF0\n   WF0\n");
+        play_rows(&mut track, 2);
+        assert_eq!(track.global_vol, 0);
+        play_rows(&mut track, 2);
+        assert_eq!(track.global_vol, 0x80);
+    }
+
+    #[test]
+    fn speed_applies_from_its_own_row() {
+        let mut track = track("   F02\n   000\n");
+        track.next();
+        assert_eq!(track.tick_rate(), 3);
+        track.next();
+        track.next();
+        track.next();
+        assert_eq!(track.row(), 1);
+    }
+
+    // Ticks spent on each row, until the song passes the first `rows`.
+    fn row_lengths(track: &mut Track, rows: usize) -> Vec<usize> {
+        let mut lengths = vec![0; rows];
+        loop {
+            track.next();
+            match lengths.get_mut(track.row()) {
+                Some(len) if track.transport() != Transport::Stopped => *len += 1,
+                _ => return lengths,
+            }
         }
     }
+
+    #[test]
+    fn grooves_repeat() {
+        let text = "   G01\n   000\n   000\n   G00\n   000\n   G05\n   F02\n   000\n   000\n";
+        let mut track = Track::new(parse_rows(text).unwrap());
+        track.grooves = vec![vec![6], vec![4, 2], vec![]];
+        track.play_song();
+        assert_eq!(row_lengths(&mut track, 8), [4, 2, 4, 6, 6, 6, 3, 3]);
+
+        // tempo is in rows, so swing keeps the average.
+        track.play_song();
+        track.next();
+        assert_eq!(track.ticks_per_minute(), 120.0 * 3.0);
+
+        // a bad groove leaves the last one playing, as does an empty one.
+        let mut track = Track::new(parse_rows("   G01\n   G09\n   G02\n   000\n").unwrap());
+        track.grooves = vec![vec![6], vec![4, 0], vec![]];
+        track.play_song();
+        assert_eq!(row_lengths(&mut track, 4), [4, 1, 4, 1]);
+    }
+
+    #[test]
+    fn fine_tempo() {
+        let mut track = track("   U80\n   000\n   U00\n");
+        track.next();
+        assert_eq!(track.ticks_per_minute(), 120.5 * 6.0);
+        play_rows(&mut track, 2);
+        assert_eq!((track.row(), track.ticks_per_minute()), (1, 120.5 * 6.0));
+        play_rows(&mut track, 1);
+        assert_eq!((track.row(), track.ticks_per_minute()), (2, 120.0 * 6.0));
+    }
 }
@@ -0,0 +1,149 @@
+use track::{Track, Channel};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Transport {
+    Stopped,
+    Song,       // play through once, stop after the last row
+    Pattern,    // wrap around to the top after the last row
+}
+
+impl Track {
+    pub fn transport(&self) -> Transport { self.transport }
+    pub fn paused(&self) -> bool { self.paused }
+    pub fn row(&self) -> usize { self.row }
+
+    pub fn stop(&mut self) {
+        self.transport = Transport::Stopped;
+        self.paused = false;
+        self.reset();
+    }
+    pub fn play_song(&mut self) {
+        self.play_from(0);
+    }
+    pub fn play_pattern(&mut self) {
+        self.start(0, Transport::Pattern);
+    }
+    pub fn play_from(&mut self, row: usize) {
+        self.start(row, Transport::Song);
+    }
+    pub fn pause(&mut self) {
+        if self.transport != Transport::Stopped {
+            self.paused = true;
+        }
+    }
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    fn start(&mut self, row: usize, mode: Transport) {
+        // with no rows there's nothing to play.
+        if self.seq.is_empty() {
+            return self.stop();
+        }
+        self.reset();
+        self.chan.resize(self.seq.width(), Channel::new());
+        self.reconstruct(row.min(self.seq.len() - 1));
+        self.transport = mode;
+        self.paused = false;
+    }
+    fn reset(&mut self) {
+        self.chan.clear();
+        self.row = 0;
+        self.row_jump = None;
+        self.tick_count = 0;
+        self.groove = vec![6];
+        self.set_groove(0);
+        self.bpm = 120;
+        self.bpm_fine = 0;
+        self.beat_bpm = false;
+        self.global_vol = 0x80;
+    }
+    // Run every row above `row` without output, so that tempo and effect
+    // memory are as if the song had played from the top. Jumps are ignored.
+    fn reconstruct(&mut self, row: usize) {
+        for r in 0..row {
+            self.row = r;
+            for i in 0..self.seq.width() {
+                self.channel_beat(i);
+            }
+            self.row_tempo();
+            self.tick_count = 0;
+            while self.tick_count < self.tick_rate() {
+                for i in 0..self.seq.width() {
+                    self.channel_tick(i);
+                }
+                self.tick_count += 1;
+            }
+            self.groove_pos = (self.groove_pos + 1) % self.groove.len();
+        }
+        self.row = row;
+        self.row_jump = None;
+        self.tick_count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use file::parse_rows;
+    use mixer::Controller;
+    use track::{Track, Transport};
+
+    const ROWS: &str = "   F02
+C-5C20
+   110
+   B00
+   000
+";
+
+    #[test]
+    fn start_mid_song() {
+        let mut track = Track::new(parse_rows(ROWS).unwrap());
+        track.play_from(3);
+        assert_eq!((track.row(), track.transport()), (3, Transport::Song));
+        // tempo, volume and the slide are as if played, but not the jump.
+        assert_eq!(track.groove, [3]);
+        assert_eq!(track.chan[0].vol, 0x20);
+        assert_eq!(track.chan[0].note, 60 << 8 | 0x200);
+        assert_eq!(track.chan[0].cmd.data, 0x10);
+        assert_eq!(track.row_jump, None);
+        track.next();
+        assert_eq!(track.row(), 3);
+
+        track.play_from(100);
+        assert_eq!(track.row(), 4);
+        track.play_pattern();
+        assert_eq!((track.row(), track.transport()), (0, Transport::Pattern));
+        assert_eq!(track.groove, [6]);
+    }
+
+    #[test]
+    fn pause_and_stop() {
+        let mut track = Track::new(parse_rows(ROWS).unwrap());
+        track.stop();
+        track.pause();
+        assert!(!track.paused());
+        track.play_song();
+        track.next();
+        track.pause();
+        let tick = track.tick_count;
+        track.next();
+        assert!(track.paused() && track.tick_count == tick);
+        track.resume();
+        track.next();
+        assert_eq!(track.tick_count, tick + 1);
+        track.stop();
+        assert_eq!((track.row(), track.transport()), (0, Transport::Stopped));
+    }
+
+    #[test]
+    fn empty_songs_stay_stopped() {
+        let mut track = Track::new(vec![]);
+        track.play_song();
+        assert_eq!(track.transport(), Transport::Stopped);
+        track.play_pattern();
+        track.play_from(3);
+        assert_eq!(track.transport(), Transport::Stopped);
+        track.next();
+        assert_eq!(track.analyze().rows, 0);
+    }
+}
@@ -0,0 +1,104 @@
+// Draws text in the bitmap font, and the highlights behind it, on a grid of
+// character cells.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use sdl2::video::{Window, WindowContext};
+
+pub const CHAR_W: i32 = 8;
+pub const CHAR_H: i32 = 8;
+
+const FONT: &'static [u8] = include_bytes!("../../res/font.bmp");
+
+pub struct Artist<'tex> {
+    canvas: Canvas<Window>,
+    font: Texture<'tex>,
+    scale: u32,
+}
+
+pub fn load_font<'tex>(tex_creator: &'tex TextureCreator<WindowContext>)
+    -> Result<Texture<'tex>, String>
+{
+    let mut surf = Surface::load_bmp_rw(&mut RWops::from_bytes(FONT)?)?;
+    surf.set_color_key(true, Color::RGB(0, 0, 0))?;
+    tex_creator.create_texture_from_surface(surf).map_err(|e| e.to_string())
+}
+
+impl<'tex> Artist<'tex> {
+    pub fn new(canvas: Canvas<Window>, font: Texture<'tex>, scale: u32) -> Self {
+        Artist {
+            canvas: canvas,
+            font: font,
+            scale: scale,
+        }
+    }
+    // Size of the window, in character cells.
+    pub fn size(&self) -> (i32, i32) {
+        let (w, h) = self.canvas.output_size().unwrap_or((0, 0));
+        (w as i32 / self.cell_w(), h as i32 / self.cell_h())
+    }
+    fn cell_w(&self) -> i32 { CHAR_W * self.scale as i32 }
+    fn cell_h(&self) -> i32 { CHAR_H * self.scale as i32 }
+
+    pub fn clear(&mut self) {
+        self.canvas.set_draw_color(Color{r:0, g:64, b:128, a:255});
+        self.canvas.clear();
+    }
+    pub fn present(&mut self) {
+        self.canvas.present();
+    }
+    pub fn print(&mut self, mut x: i32, y: i32, chars: &str) {
+        for c in chars.as_bytes() {
+            let src = Rect::new(
+                (*c as i32 % 16) * CHAR_W,
+                (*c as i32 / 16) * CHAR_H,
+                CHAR_W as u32,
+                CHAR_H as u32);
+            let dest = Rect::new(
+                x * self.cell_w(),
+                y * self.cell_h(),
+                self.cell_w() as u32,
+                self.cell_h() as u32);
+            self.canvas.copy(&self.font, Some(src), Some(dest)).unwrap();
+            x += 1;
+        }
+    }
+    pub fn playback_line(&mut self, row: i32) {
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let (w, _) = self.canvas.output_size().unwrap_or((0, 0));
+        self.canvas.draw_rect(Rect::new(
+                0, row * self.cell_h(),
+                w, self.cell_h() as u32))
+            .unwrap();
+    }
+    pub fn cursor(&mut self, x: i32, y: i32, w: u32, h: u32) {
+        self.canvas.set_draw_color(Color::RGB(128, 0, 0));
+        self.canvas.fill_rect(Rect::new(
+                x * self.cell_w() - 1,
+                y * self.cell_h() - 1,
+                w * self.cell_w() as u32 + 2,
+                h * self.cell_h() as u32 + 2))
+            .unwrap();
+    }
+    pub fn selection(&mut self, x: i32, y: i32, w: u32, h: u32) {
+        self.canvas.set_draw_color(Color::RGB(0, 80, 0));
+        self.canvas.fill_rect(Rect::new(
+                x * self.cell_w(),
+                y * self.cell_h(),
+                w * self.cell_w() as u32,
+                h * self.cell_h() as u32))
+            .unwrap();
+    }
+    pub fn bg(&mut self, x: i32, y: i32, w: u32, h: u32) {
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.fill_rect(Rect::new(
+                x * self.cell_w(),
+                y * self.cell_h(),
+                w * self.cell_w() as u32,
+                h * self.cell_h() as u32))
+            .unwrap();
+    }
+}
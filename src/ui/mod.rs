@@ -1,39 +1,174 @@
 use sdl2;
 
+mod artist;
 mod keyboard;
 
-use std::sync::{Mutex, Arc};
-use sequence::{Field, Note, Command};
-use track::Track;
-use mixer::{Controller, MixerIn};
+use hztrack::editor::Action;
+use hztrack::mixer::Settings;
+use hztrack::track::{Edit, Transport};
+use hztrack::keymap::{self, Keymap, Bound, Chord, Key};
+use session::{self, Session};
+
+use self::artist::Artist;
+
+const SCALE: u32 = 2;
+const GUTTER: i32 = 4;      // row numbers on the left
+const FIELD_W: i32 = 7;     // a field, and a space between channels
 
-#[derive(Clone)]
 struct Ui {
-    track: Arc<Mutex<Track>>,
+    session:    Session,
+    keymap:     Keymap,
+    top:        usize,  // first row in view
+    clipboard:  sdl2::clipboard::ClipboardUtil, // blocks go here as text
 }
-impl Controller for Ui {
-    fn next(&mut self) -> MixerIn {
-        let mut track = self.track.lock().unwrap();
-        track.next()
+
+impl Ui {
+    // Returns false to quit.
+    fn key(&mut self, chord: Chord) -> bool {
+        let action = match self.keymap.bound(&chord).cloned() {
+            Some(Bound::Edit(action)) => action,
+            Some(Bound::PlaySong) => return self.send(Edit::PlaySong),
+            Some(Bound::PlayFrom) => {
+                let row = self.session.editor.row();
+                return self.send(Edit::PlayFrom(row));
+            }
+            Some(Bound::Stop) => return self.send(Edit::Stop),
+            Some(Bound::Pause) => {
+                let edit = if self.session.remote.status().paused {
+                    Edit::Resume
+                } else {
+                    Edit::Pause
+                };
+                return self.send(edit);
+            }
+            Some(Bound::Quit) => return false,
+            // there's no file to save to yet.
+            Some(Bound::Save) => return true,
+            None => {
+                let column = self.session.editor.column();
+                match chord.key {
+                    Key::Char(c) if !chord.ctrl && !chord.alt => {
+                        match self.keymap.entry(column, c) {
+                            Some(action) => action,
+                            None => return true,
+                        }
+                    }
+                    _ => return true,
+                }
+            }
+        };
+        match action {
+            // take blocks copied in other programs, or other instances;
+            // any other text leaves the internal clipboard to paste.
+            Action::Paste | Action::MixPaste | Action::PasteFlood => {
+                if let Ok(text) = self.clipboard.clipboard_text() {
+                    let _ = self.session.editor.set_clipboard_text(&text);
+                }
+            }
+            _ => {},
+        }
+        let copies = matches!(action, Action::Copy | Action::Cut);
+        self.session.act(action);
+        if copies {
+            let text = self.session.editor.clipboard_text();
+            let _ = self.clipboard.set_clipboard_text(&text);
+        }
+        true
+    }
+    fn send(&mut self, edit: Edit) -> bool {
+        self.session.send(edit);
+        true
+    }
+    fn draw(&mut self, artist: &mut Artist) {
+        let status = self.session.remote.status().clone();
+        let playing = status.transport != Transport::Stopped;
+        let editor = &self.session.editor;
+        let seq = editor.seq();
+        // the bottom line is for the status line.
+        let (_, height) = artist.size();
+        let height = (height - 1).max(1) as usize;
+        // follow playback, or else the cursor.
+        let focus = if playing { status.row } else { editor.row() };
+        if focus < self.top {
+            self.top = focus;
+        } else if focus >= self.top + height {
+            self.top = focus + 1 - height;
+        }
+        let rows = (seq.len() - self.top.min(seq.len())).min(height);
+
+        artist.clear();
+        artist.bg(GUTTER, 0, (seq.width() as i32 * FIELD_W - 1) as u32, rows as u32);
+        if editor.marked() {
+            let b = editor.block();
+            let top = b.row.max(self.top);
+            let bottom = (b.row + b.height).min(self.top + rows);
+            if top < bottom {
+                artist.selection(
+                    GUTTER + b.chan as i32 * FIELD_W, (top - self.top) as i32,
+                    (b.width as i32 * FIELD_W - 1) as u32, (bottom - top) as u32);
+            }
+        }
+        if editor.row() >= self.top && editor.row() < self.top + rows {
+            let (a, b) = editor.column().span();
+            let x = GUTTER + editor.chan() as i32 * FIELD_W + a as i32;
+            artist.cursor(x, (editor.row() - self.top) as i32, (b - a) as u32, 1);
+        }
+        for y in 0..rows {
+            let row = self.top + y;
+            artist.print(0, y as i32, &format!("{:3}", row));
+            for (x, field) in seq.fields[row].iter().enumerate() {
+                artist.print(GUTTER + x as i32 * FIELD_W, y as i32, &format!("{}", field));
+            }
+        }
+        if playing && status.row >= self.top && status.row < self.top + rows {
+            artist.playback_line((status.row - self.top) as i32);
+        }
+        let state = match (status.transport, status.paused) {
+            (Transport::Stopped, _) => "stopped",
+            (_, true) => "paused",
+            (Transport::Song, _) => "playing",
+            (Transport::Pattern, _) => "looping",
+        };
+        artist.print(0, height as i32, &format!("octave {}  step {}  row {}/{}  chan {}  {}",
+            editor.octave(), editor.edit_step(), editor.row(), seq.len(),
+            editor.chan() + 1, state));
+        artist.present();
     }
 }
+
 pub fn run() {
+    let keymap = keymap::load().unwrap_or_else(|e| {
+        eprintln!("hztrack: {}", e);
+        ::std::process::exit(1);
+    });
     let sdl = sdl2::init().unwrap();
 
-    let track = Track::new(vec![vec![
-        Field{note: Note::Off, cmd: Command::zero()}
-    ]]);
-    let ui = Ui{
-        track: Arc::new(Mutex::new(track)),
+    let mut track = session::new_song();
+    track.stop();
+    let (session, player) = Session::new(track);
+    let video_subsys = sdl.video().unwrap();
+    let mut ui = Ui {
+        session: session,
+        keymap: keymap,
+        top: 0,
+        clipboard: video_subsys.clipboard(),
     };
 
-    let video_subsys = sdl.video().unwrap();
-    let _win = video_subsys.window("rusttracker", 800, 600)
+    let win = video_subsys.window("rusttracker", 800, 600)
         .position_centered()
         .opengl()
         .resizable()
         .build().unwrap();
-    let _mixer = ::mixer::run(&sdl, ui.clone());
+    let canvas = win.into_canvas()
+        .accelerated()
+        .present_vsync()
+        .target_texture()
+        .build().unwrap();
+    let tex_creator = canvas.texture_creator();
+    let font = artist::load_font(&tex_creator).unwrap();
+    let mut artist = Artist::new(canvas, font, SCALE);
+    let mut audio = ::audio::open_in(&sdl, &::audio::default_output()).unwrap();
+    audio.start(Box::new(player), &Settings::default()).unwrap();
     let mut event_pump = sdl.event_pump().unwrap();
 
     'main: loop {
@@ -41,16 +176,25 @@ pub fn run() {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit{..}  => break 'main,
-                Event::KeyDown{scancode, ..} => {
-                    // HACK: play note, bring into audible octave
-                    let mut track = ui.track.lock().unwrap();
-                    track.seq.fields[0][0].note = keyboard::to_note(scancode.unwrap());
-                    if let Note::On(n) = track.seq.fields[0][0].note {
-                        track.seq.fields[0][0].note = Note::On(n+48);
+                Event::KeyDown{scancode: Some(sc), keymod, ..}
+                if keyboard::to_channel(sc).is_some() => {
+                    use sdl2::keyboard::{LSHIFTMOD, RSHIFTMOD};
+                    let chan = keyboard::to_channel(sc).unwrap();
+                    if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                        ui.session.send(Edit::ToggleSolo(chan));
+                    } else {
+                        ui.session.send(Edit::ToggleMute(chan));
+                    }
+                }
+                Event::KeyDown{scancode: Some(sc), keycode, keymod, ..} => {
+                    let chord = keyboard::to_chord(sc, keycode, keymod);
+                    if !chord.is_none_or(|chord| ui.key(chord)) {
+                        break 'main;
                     }
                 }
                 _ => {},
             }
         }
+        ui.draw(&mut artist);
     }
 }